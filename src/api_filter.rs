@@ -1,34 +1,567 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::ops::Deref;
+
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::model::{APILocation, Allergen, Language, Meal};
+use crate::diagnostics::{ScrapeWarning, WarningKind};
+use crate::grouping::{DayListing, GroupBy};
+use crate::history::HistoryEntry;
+use crate::languages::{ServedLanguages, DEFAULT_LANGUAGE};
+use crate::model::{APILocation, Allergen, Coordinates, Data, Meal, PriceCategory};
+use crate::opening_hours::TIME_ZONE;
+use crate::search::SearchQuery;
 
 pub trait APIFilter<T>: for<'a> Deserialize<'a> + Send {
     fn accepts(&self, to_filter: &T) -> bool;
-    fn filter<'a>(&self, to_be_filtered: &'a Vec<T>) -> Vec<&'a T> {
+    fn filter<'a>(&self, to_be_filtered: &'a [T]) -> Vec<&'a T> {
         to_be_filtered.iter().filter(|t| self.accepts(t)).collect()
     }
+
+    /// Comma separated sort keys, each optionally suffixed with `:asc` or `:desc`
+    fn sort(&self) -> Option<&str>;
+    fn limit(&self) -> Option<usize>;
+    fn offset(&self) -> Option<usize>;
+
+    /// Negotiates the served languages from the requested languages, the Accept-Language header
+    /// and the languages the data is available in. Does nothing for data without languages.
+    fn negotiate_languages(&mut self, _accept_language: Option<&str>, _data: &Data) {}
+
+    /// The languages the response is served in. Only known after negotiation.
+    fn content_language(&self) -> Option<String> {
+        None
+    }
+
+    /// Filters, sorts and paginates the given elements
+    fn query<'a>(&self, to_be_queried: &'a [T]) -> Result<Page<&'a T>, QueryError>
+    where
+        T: Sortable,
+    {
+        let page = self.query_positions(to_be_queried)?;
+        Ok(Page {
+            total: page.total,
+            items: page.items.into_iter().map(|i| &to_be_queried[i]).collect(),
+        })
+    }
+
+    /// Like [`APIFilter::query`], but returns the positions of the elements in the given slice
+    fn query_positions(&self, to_be_queried: &[T]) -> Result<Page<usize>, QueryError>
+    where
+        T: Sortable,
+    {
+        let mut result: Vec<usize> = (0..to_be_queried.len())
+            .filter(|&i| self.accepts(&to_be_queried[i]))
+            .collect();
+        sort_by_keys_of(&mut result, self.sort(), |&i| &to_be_queried[i])?;
+        Ok(Page::new(result, self.offset(), self.limit()))
+    }
+}
+
+/// An error caused by invalid query parameters
+#[derive(Debug)]
+pub struct QueryError(String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl QueryError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self(message.into())
+    }
+}
+
+pub trait Sortable {
+    /// The keys that can be passed to [`Sortable::compare_by`]
+    const SORT_KEYS: &'static [&'static str];
+    fn compare_by(&self, other: &Self, key: &str) -> Ordering;
+}
+
+/// Sorts the elements stable by the given comma separated sort keys.
+/// Fails if one of the keys is unknown to `T`.
+pub fn sort_by_keys<T: Sortable>(
+    elements: &mut [&T],
+    sort: Option<&str>,
+) -> Result<(), QueryError> {
+    sort_by_keys_of(elements, sort, |element| *element)
+}
+
+/// Sorts the elements stable by the given comma separated sort keys of the item of every element,
+/// e.g. positions by the items at the positions. Fails if one of the keys is unknown to `T`.
+pub fn sort_by_keys_of<'a, E, T: Sortable + 'a>(
+    elements: &mut [E],
+    sort: Option<&str>,
+    item: impl Fn(&E) -> &'a T,
+) -> Result<(), QueryError> {
+    let Some(sort) = sort else {
+        return Ok(());
+    };
+
+    let keys = sort
+        .split(",")
+        .map(|key| {
+            let (key, descending) = match key.split_once(":") {
+                Some((key, "asc")) => (key, false),
+                Some((key, "desc")) => (key, true),
+                Some((_, order)) => {
+                    return Err(QueryError(format!(
+                        "Invalid sort order '{order}'. Expected 'asc' or 'desc'"
+                    )))
+                }
+                None => (key, false),
+            };
+            if !T::SORT_KEYS.contains(&key) {
+                return Err(QueryError(format!(
+                    "Invalid sort key '{key}'. Expected one of: {}",
+                    T::SORT_KEYS.join(", ")
+                )));
+            }
+            Ok((key, descending))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    elements.sort_by(|a, b| {
+        keys.iter()
+            .map(|(key, descending)| {
+                let ordering = item(a).compare_by(item(b), key);
+                if *descending {
+                    ordering.reverse()
+                } else {
+                    ordering
+                }
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+
+    Ok(())
+}
+
+/// A slice of the query result together with the size of the whole result
+#[derive(Debug)]
+pub struct Page<T> {
+    pub total: usize,
+    pub items: Vec<T>,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, offset: Option<usize>, limit: Option<usize>) -> Self {
+        Self {
+            total: items.len(),
+            items: items
+                .into_iter()
+                .skip(offset.unwrap_or(0))
+                .take(limit.unwrap_or(usize::MAX))
+                .collect(),
+        }
+    }
+}
+
+impl Sortable for Meal {
+    const SORT_KEYS: &'static [&'static str] = &[
+        "date",
+        "location",
+        "name",
+        "price.students",
+        "price.employees",
+        "price.guests",
+        "language",
+    ];
+
+    fn compare_by(&self, other: &Self, key: &str) -> Ordering {
+        match key {
+            "date" => self.date.cmp(&other.date),
+            "location" => self.location.code.cmp(&other.location.code),
+            "name" => self.name.cmp(&other.name),
+            "price.students" => compare_prices(self, other, PriceCategory::Students),
+            "price.employees" => compare_prices(self, other, PriceCategory::Employees),
+            "price.guests" => compare_prices(self, other, PriceCategory::Guests),
+            "language" => self.language.code.cmp(&other.language.code),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+fn compare_prices(a: &Meal, b: &Meal, category: PriceCategory) -> Ordering {
+    a.price.get(category).total_cmp(&b.price.get(category))
+}
+
+impl Sortable for Allergen {
+    const SORT_KEYS: &'static [&'static str] = &["code", "name", "language"];
+
+    fn compare_by(&self, other: &Self, key: &str) -> Ordering {
+        match key {
+            "code" => self.code.cmp(&other.code),
+            "name" => self.name.cmp(&other.name),
+            "language" => self.language.code.cmp(&other.language.code),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+impl Sortable for APILocation {
+    const SORT_KEYS: &'static [&'static str] = &["code", "name", "city"];
+
+    fn compare_by(&self, other: &Self, key: &str) -> Ordering {
+        match key {
+            "code" => self.code.cmp(&other.code),
+            "name" => self.name.cmp(&other.name),
+            "city" => self.city.cmp(&other.city),
+            _ => Ordering::Equal,
+        }
+    }
+}
+impl Sortable for ScrapeWarning {
+    const SORT_KEYS: &'static [&'static str] = &["location", "week", "language", "kind"];
+
+    fn compare_by(&self, other: &Self, key: &str) -> Ordering {
+        match key {
+            "location" => self.location.cmp(&other.location),
+            "week" => self.week.cmp(&other.week),
+            "language" => self.language.cmp(&other.language),
+            "kind" => self.kind.cmp(&other.kind),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// Checks the language against the requested language codes. Defaults to german.
+fn accepts_language(language: &Option<CommaSeparated>, language_code: &str) -> bool {
+    match language {
+        Some(language) => language.contains(language_code),
+        None => language_code == DEFAULT_LANGUAGE,
+    }
+}
+
+/// Checks the language against the languages negotiated for the location.
+/// Falls back to the requested language codes without negotiation.
+fn accepts_language_at(
+    served_languages: &Option<ServedLanguages>,
+    language: &Option<CommaSeparated>,
+    location_code: &str,
+    language_code: &str,
+) -> bool {
+    match served_languages {
+        Some(served) => served.accepts_at(location_code, language_code),
+        None => accepts_language(language, language_code),
+    }
 }
+
+/// The codes of the languages meals can be served in, to look the meals up by language
+fn meal_languages(
+    served_languages: &Option<ServedLanguages>,
+    language: &Option<CommaSeparated>,
+) -> Vec<String> {
+    match (served_languages, language) {
+        (Some(served), _) => served.codes_at_locations(),
+        (None, Some(language)) => language.to_vec(),
+        (None, None) => vec![DEFAULT_LANGUAGE.to_string()],
+    }
+}
+
+/// The values of a comma separated query parameter, split once when the query is parsed.
 // Warp currently does not support vec. So I parse those manually with ',' as separator: https://github.com/seanmonstar/warp/issues/732
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct CommaSeparated(Vec<String>);
+
+impl CommaSeparated {
+    pub fn contains(&self, value: &str) -> bool {
+        self.0.iter().any(|v| v == value)
+    }
+}
+
+impl Deref for CommaSeparated {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for CommaSeparated {
+    fn from_iter<I: IntoIterator<Item = S>>(values: I) -> Self {
+        Self(
+            values
+                .into_iter()
+                .map(|value| value.as_ref().to_string())
+                .collect(),
+        )
+    }
+}
+
+impl From<String> for CommaSeparated {
+    fn from(values: String) -> Self {
+        values.split(",").collect()
+    }
+}
+
+impl From<CommaSeparated> for String {
+    fn from(value: CommaSeparated) -> Self {
+        value.0.join(",")
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct MealsQuery {
-    date: Option<String>,
-    location: Option<String>,
-    exclude_allergens: Option<String>,
+    /// Comma separated dates in the format YYYY-MM-DD
+    #[param(value_type = Option<String>)]
+    date: Option<CommaSeparated>,
+    /// Comma separated location codes
+    #[param(value_type = Option<String>)]
+    location: Option<CommaSeparated>,
+    /// Comma separated allergen codes the meals must not contain
+    #[param(value_type = Option<String>)]
+    exclude_allergens: Option<CommaSeparated>,
     vegan: Option<bool>,
     vegetarian: Option<bool>,
-    language: Option<String>,
+    /// Comma separated language codes. Takes precedence over the Accept-Language header
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+    /// The minimum price in the price category. Meals without a price are left out
+    min_price: Option<f32>,
+    /// The maximum price in the price category. Meals without a price are left out
+    max_price: Option<f32>,
+    /// The price category of the price filters, the price sorting and `category_price`. Default: students
+    price_category: Option<PriceCategory>,
+    /// Groups the meals. Empty groups are days a location is closed. Days without meals matching the filters and days without data are left out. Pagination applies to the groups
+    group_by: Option<GroupBy>,
+    /// Full text search over the meal names. Tolerates typos and umlaut spellings
+    #[param(value_type = Option<String>)]
+    q: Option<SearchQuery>,
+    /// Comma separated sort keys, each optionally suffixed with ':asc' or ':desc'.
+    /// Available keys: date, location, name, price.students, price.employees, price.guests, language
+    sort: Option<String>,
+    /// The maximum number of elements
+    limit: Option<usize>,
+    /// The number of elements to skip
+    offset: Option<usize>,
+    #[serde(skip)]
+    #[param(ignore)]
+    served_languages: Option<ServedLanguages>,
 }
 
-impl APIFilter<Meal> for MealsQuery {
-    fn accepts(&self, meal: &Meal) -> bool {
-        self.date
+/// Builds the query, e.g. to request meals with the client
+impl MealsQuery {
+    pub fn with_dates(mut self, dates: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.date = Some(dates.into_iter().collect());
+        self
+    }
+
+    pub fn with_locations(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.location = Some(codes.into_iter().collect());
+        self
+    }
+
+    pub fn with_excluded_allergens(
+        mut self,
+        codes: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self {
+        self.exclude_allergens = Some(codes.into_iter().collect());
+        self
+    }
+
+    pub fn with_vegan(mut self, vegan: bool) -> Self {
+        self.vegan = Some(vegan);
+        self
+    }
+
+    pub fn with_vegetarian(mut self, vegetarian: bool) -> Self {
+        self.vegetarian = Some(vegetarian);
+        self
+    }
+
+    pub fn with_languages(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.language = Some(codes.into_iter().collect());
+        self
+    }
+
+    pub fn with_min_price(mut self, min_price: f32) -> Self {
+        self.min_price = Some(min_price);
+        self
+    }
+
+    pub fn with_max_price(mut self, max_price: f32) -> Self {
+        self.max_price = Some(max_price);
+        self
+    }
+
+    pub fn with_price_category(mut self, price_category: PriceCategory) -> Self {
+        self.price_category = Some(price_category);
+        self
+    }
+
+    pub fn with_group_by(mut self, group_by: GroupBy) -> Self {
+        self.group_by = Some(group_by);
+        self
+    }
+
+    pub fn with_search(mut self, q: impl Into<String>) -> Self {
+        self.q = Some(SearchQuery::from(q.into()));
+        self
+    }
+
+    /// Sort keys, each optionally suffixed with `:asc` or `:desc`
+    pub fn with_sort(mut self, keys: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.sort = Some(CommaSeparated::from_iter(keys).into());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+impl MealsQuery {
+    /// The price category explicitly requested by the client
+    pub fn price_category(&self) -> Option<PriceCategory> {
+        self.price_category
+    }
+
+    pub fn group_by(&self) -> Option<GroupBy> {
+        self.group_by
+    }
+
+    /// The language of the labels of the response, e.g. location names
+    pub fn label_language(&self) -> &str {
+        self.served_languages
             .as_ref()
-            .map(|d| d.contains(&meal.date))
+            .map(|served| served.primary())
+            .unwrap_or(DEFAULT_LANGUAGE)
+    }
+
+    /// Sorts the positions of the meals by the requested sort keys of the meals.
+    /// Without sort keys the results of a search are ranked by relevance.
+    pub fn sort_meal_positions(
+        &self,
+        meals: &[Meal],
+        positions: &mut Vec<usize>,
+    ) -> Result<(), QueryError> {
+        match (self.sort(), &self.q) {
+            (None, Some(search)) => {
+                let mut ranked: Vec<_> = positions
+                    .drain(..)
+                    .map(|i| (search.score(&meals[i].name).unwrap_or_default(), i))
+                    .collect();
+                ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+                positions.extend(ranked.into_iter().map(|(_, i)| i));
+                Ok(())
+            }
+            (sort, _) => sort_by_keys_of(positions, sort, |&i| &meals[i]),
+        }
+    }
+
+    fn listed_price(&self, meal: &Meal) -> Option<f32> {
+        meal.price
+            .get_listed(self.price_category.unwrap_or_default())
+    }
+
+    pub fn accepts_date(&self, date: &str) -> bool {
+        self.date.as_ref().map(|d| d.contains(date)).unwrap_or(true)
+    }
+
+    /// Filters the meals of the data. Looks the meals up by date, location and language first,
+    /// so only the meals found are checked against the other filters.
+    pub fn filter_meals<'a>(&self, data: &'a Data) -> Vec<&'a Meal> {
+        self.filter_meal_positions(data)
+            .into_iter()
+            .map(|i| &data.get_meals()[i])
+            .collect()
+    }
+
+    /// The positions of the meals [`MealsQuery::filter_meals`] returns in the meals of the data
+    pub fn filter_meal_positions(&self, data: &Data) -> Vec<usize> {
+        data.find_meal_positions(
+            self.date.as_deref(),
+            self.location.as_deref(),
+            Some(&meal_languages(&self.served_languages, &self.language)),
+        )
+        .into_iter()
+        .filter(|&i| self.accepts(&data.get_meals()[i]))
+        .collect()
+    }
+
+    /// Filters the meals of the data like [`MealsQuery::filter_meals`], then sorts and paginates them
+    pub fn query_meals<'a>(&self, data: &'a Data) -> Result<Page<&'a Meal>, QueryError> {
+        let mut result = self.filter_meals(data);
+        sort_by_keys(&mut result, self.sort())?;
+        Ok(Page::new(result, self.offset(), self.limit()))
+    }
+
+    /// The days the menu pages of the data list, in the languages the query serves
+    pub fn listing<'a>(&'a self, data: &'a Data) -> QueryListing<'a> {
+        QueryListing { query: self, data }
+    }
+
+    /// Whether meals of the location are served in the language
+    pub fn accepts_language_at(&self, location_code: &str, language_code: &str) -> bool {
+        accepts_language_at(
+            &self.served_languages,
+            &self.language,
+            location_code,
+            language_code,
+        )
+    }
+
+    pub fn accepts_location(&self, location: &APILocation) -> bool {
+        self.location
+            .as_ref()
+            .map(|l| l.contains(&location.code))
             .unwrap_or(true)
+    }
+}
+
+/// The days the menu pages list, in the languages a query serves
+pub struct QueryListing<'a> {
+    query: &'a MealsQuery,
+    data: &'a Data,
+}
+
+impl DayListing for QueryListing<'_> {
+    fn lists(&self, location: &APILocation, date: &str) -> bool {
+        self.data.get_slices().iter().any(|slice| {
+            slice.lists(location.get_code(), date)
+                && self
+                    .query
+                    .accepts_language_at(location.get_code(), slice.get_language())
+        })
+    }
+
+    fn serves(&self, location: &APILocation, date: &str) -> bool {
+        self.data
+            .find_meals(
+                Some(&[date.to_string()]),
+                Some(std::slice::from_ref(&location.code)),
+                None,
+            )
+            .iter()
+            .any(|meal| {
+                self.query
+                    .accepts_language_at(&meal.location.code, &meal.language.code)
+            })
+    }
+}
+
+impl APIFilter<Meal> for MealsQuery {
+    fn accepts(&self, meal: &Meal) -> bool {
+        self.accepts_date(&meal.date)
+            && self.accepts_location(&meal.location)
             && self
-                .location
+                .q
                 .as_ref()
-                .map(|d| d.contains(&meal.location.code))
+                .map(|q| q.score(&meal.name).is_some())
                 .unwrap_or(true)
             && self
                 .exclude_allergens
@@ -51,22 +584,219 @@ impl APIFilter<Meal> for MealsQuery {
                 .map(|vegetarian| &meal.vegetarian == vegetarian)
                 .unwrap_or(true)
             && self
-                .language
-                .as_ref()
-                .cloned()
-                .unwrap_or_else(|| Language::german().code)
-                .split(",")
-                .collect::<Vec<_>>()
-                .contains(&meal.language.code.as_str())
+                .min_price
+                .map(|min| self.listed_price(meal).is_some_and(|price| price >= min))
+                .unwrap_or(true)
+            && self
+                .max_price
+                .map(|max| self.listed_price(meal).is_some_and(|price| price <= max))
+                .unwrap_or(true)
+            && accepts_language_at(
+                &self.served_languages,
+                &self.language,
+                &meal.location.code,
+                &meal.language.code,
+            )
+    }
+
+    fn negotiate_languages(&mut self, accept_language: Option<&str>, data: &Data) {
+        self.served_languages = Some(ServedLanguages::negotiate(
+            self.language.as_deref(),
+            accept_language,
+            data.get_languages(),
+            data.get_locations()
+                .iter()
+                .filter(|location| self.accepts_location(location)),
+        ));
+    }
+
+    fn content_language(&self) -> Option<String> {
+        self.served_languages
+            .as_ref()
+            .map(|served| served.content_language(true))
+    }
+
+    fn sort(&self) -> Option<&str> {
+        self.sort.as_deref()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NextMealsQuery {
+    /// Full text search over the meal names
+    #[param(value_type = String)]
+    q: SearchQuery,
+    /// Comma separated location codes
+    #[param(value_type = Option<String>)]
+    location: Option<CommaSeparated>,
+    /// Comma separated language codes. Takes precedence over the Accept-Language header
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+    /// The maximum number of elements
+    limit: Option<usize>,
+    /// The number of elements to skip
+    offset: Option<usize>,
+    #[serde(skip, default = "today")]
+    #[param(ignore)]
+    today: String,
+    #[serde(skip)]
+    #[param(ignore)]
+    served_languages: Option<ServedLanguages>,
+}
+
+fn today() -> String {
+    chrono::Utc::now()
+        .with_timezone(&TIME_ZONE)
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+impl NextMealsQuery {
+    fn accepts_location(&self, location_code: &str) -> bool {
+        self.location
+            .as_ref()
+            .map(|l| l.contains(location_code))
+            .unwrap_or(true)
+    }
+
+    /// Filters, sorts and paginates the meals of the data. Looks the meals up by location and language first.
+    /// Returns the positions of the meals in the meals of the data.
+    pub fn query_meal_positions(&self, data: &Data) -> Result<Page<usize>, QueryError> {
+        let meals = data.get_meals();
+        let mut result: Vec<usize> = data
+            .find_meal_positions(
+                None,
+                self.location.as_deref(),
+                Some(&meal_languages(&self.served_languages, &self.language)),
+            )
+            .into_iter()
+            .filter(|&i| self.accepts(&meals[i]))
+            .collect();
+        sort_by_keys_of(&mut result, self.sort(), |&i| &meals[i])?;
+        Ok(Page::new(result, self.offset(), self.limit()))
+    }
+
+    /// Checks if the entry is a matching meal that was served before today
+    pub fn accepts_past(&self, entry: &HistoryEntry) -> bool {
+        entry.date < self.today
+            && self.accepts_location(&entry.location)
+            && accepts_language_at(
+                &self.served_languages,
+                &self.language,
+                &entry.location,
+                &entry.language,
+            )
+            && self.q.score(&entry.name).is_some()
+    }
+}
+
+impl APIFilter<Meal> for NextMealsQuery {
+    fn accepts(&self, meal: &Meal) -> bool {
+        meal.date >= self.today
+            && self.accepts_location(&meal.location.code)
+            && accepts_language_at(
+                &self.served_languages,
+                &self.language,
+                &meal.location.code,
+                &meal.language.code,
+            )
+            && self.q.score(&meal.name).is_some()
+    }
+
+    fn negotiate_languages(&mut self, accept_language: Option<&str>, data: &Data) {
+        self.served_languages = Some(ServedLanguages::negotiate(
+            self.language.as_deref(),
+            accept_language,
+            data.get_languages(),
+            data.get_locations()
+                .iter()
+                .filter(|location| self.accepts_location(&location.code)),
+        ));
+    }
+
+    fn content_language(&self) -> Option<String> {
+        self.served_languages
+            .as_ref()
+            .map(|served| served.content_language(true))
+    }
+
+    fn sort(&self) -> Option<&str> {
+        Some("date,location")
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn offset(&self) -> Option<usize> {
+        self.offset
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct AllergensQuery {
-    code: Option<String>,
-    name: Option<String>,
-    location: Option<String>,
-    language: Option<String>,
+    /// Comma separated allergen codes
+    #[param(value_type = Option<String>)]
+    code: Option<CommaSeparated>,
+    /// Comma separated allergen names
+    #[param(value_type = Option<String>)]
+    name: Option<CommaSeparated>,
+    /// Comma separated language codes. Takes precedence over the Accept-Language header
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+    /// Comma separated sort keys, each optionally suffixed with ':asc' or ':desc'. Available keys: code, name, language
+    sort: Option<String>,
+    /// The maximum number of elements
+    limit: Option<usize>,
+    /// The number of elements to skip
+    offset: Option<usize>,
+    #[serde(skip)]
+    #[param(ignore)]
+    served_languages: Option<ServedLanguages>,
+}
+
+/// Builds the query, e.g. to request allergens with the client
+impl AllergensQuery {
+    pub fn with_codes(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.code = Some(codes.into_iter().collect());
+        self
+    }
+
+    pub fn with_names(mut self, names: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.name = Some(names.into_iter().collect());
+        self
+    }
+
+    pub fn with_languages(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.language = Some(codes.into_iter().collect());
+        self
+    }
+
+    /// Sort keys, each optionally suffixed with `:asc` or `:desc`
+    pub fn with_sort(mut self, keys: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.sort = Some(CommaSeparated::from_iter(keys).into());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
 }
 
 impl APIFilter<Allergen> for AllergensQuery {
@@ -80,22 +810,191 @@ impl APIFilter<Allergen> for AllergensQuery {
                 .as_ref()
                 .map(|n| n.contains(&allergen.code))
                 .unwrap_or(true)
-            && self
-                .language
-                .as_ref()
-                .cloned()
-                .unwrap_or_else(|| Language::german().code)
-                .split(",")
-                .collect::<Vec<_>>()
-                .contains(&allergen.language.code.as_str())
+            && match &self.served_languages {
+                Some(served) => served.accepts(&allergen.language.code),
+                None => accepts_language(&self.language, &allergen.language.code),
+            }
+    }
+
+    fn negotiate_languages(&mut self, accept_language: Option<&str>, data: &Data) {
+        self.served_languages = Some(ServedLanguages::negotiate(
+            self.language.as_deref(),
+            accept_language,
+            data.get_languages(),
+            std::iter::empty(),
+        ));
+    }
+
+    fn content_language(&self) -> Option<String> {
+        self.served_languages
+            .as_ref()
+            .map(|served| served.content_language(false))
+    }
+
+    fn sort(&self) -> Option<&str> {
+        self.sort.as_deref()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn offset(&self) -> Option<usize> {
+        self.offset
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct LocationsQuery {
-    code: Option<String>,
-    name: Option<String>,
-    city: Option<String>,
+    /// Comma separated location codes
+    #[param(value_type = Option<String>)]
+    code: Option<CommaSeparated>,
+    /// Comma separated location names
+    #[param(value_type = Option<String>)]
+    name: Option<CommaSeparated>,
+    /// Comma separated cities
+    #[param(value_type = Option<String>)]
+    city: Option<CommaSeparated>,
+    /// The latitude of the position the distance is measured from. Requires `lon`
+    lat: Option<f64>,
+    /// The longitude of the position the distance is measured from. Requires `lat`
+    lon: Option<f64>,
+    /// Embeds the meals of the location
+    with_meals: Option<WithMeals>,
+    /// The language of the embedded meals. The first language is also used for the names and cities
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+    /// Comma separated sort keys, each optionally suffixed with ':asc' or ':desc'. Available keys: code, name, city
+    sort: Option<String>,
+    /// The maximum number of elements
+    limit: Option<usize>,
+    /// The number of elements to skip
+    offset: Option<usize>,
+    #[serde(skip, default = "today")]
+    #[param(ignore)]
+    today: String,
+    #[serde(skip)]
+    #[param(ignore)]
+    served_languages: Option<ServedLanguages>,
+}
+
+/// The meals that are embedded into locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WithMeals {
+    Today,
+}
+
+impl Default for LocationsQuery {
+    fn default() -> Self {
+        Self {
+            code: None,
+            name: None,
+            city: None,
+            lat: None,
+            lon: None,
+            with_meals: None,
+            language: None,
+            sort: None,
+            limit: None,
+            offset: None,
+            today: today(),
+            served_languages: None,
+        }
+    }
+}
+
+/// Builds the query, e.g. to request locations with the client
+impl LocationsQuery {
+    pub fn with_codes(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.code = Some(codes.into_iter().collect());
+        self
+    }
+
+    pub fn with_names(mut self, names: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.name = Some(names.into_iter().collect());
+        self
+    }
+
+    pub fn with_cities(mut self, cities: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.city = Some(cities.into_iter().collect());
+        self
+    }
+
+    /// The position the distance of the locations is measured from
+    pub fn with_origin(mut self, lat: f64, lon: f64) -> Self {
+        self.lat = Some(lat);
+        self.lon = Some(lon);
+        self
+    }
+
+    pub fn with_embedded_meals(mut self, with_meals: WithMeals) -> Self {
+        self.with_meals = Some(with_meals);
+        self
+    }
+
+    pub fn with_languages(mut self, codes: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.language = Some(codes.into_iter().collect());
+        self
+    }
+
+    /// Sort keys, each optionally suffixed with `:asc` or `:desc`
+    pub fn with_sort(mut self, keys: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.sort = Some(CommaSeparated::from_iter(keys).into());
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
+
+impl LocationsQuery {
+    /// The language of the labels of the response, e.g. location names
+    pub fn label_language(&self) -> &str {
+        self.served_languages
+            .as_ref()
+            .map(|served| served.primary())
+            .unwrap_or(DEFAULT_LANGUAGE)
+    }
+
+    /// The position the distance of the locations is measured from
+    pub fn origin(&self) -> Option<Coordinates> {
+        Some(Coordinates {
+            lat: self.lat?,
+            lon: self.lon?,
+        })
+    }
+
+    /// The meals to embed into the location. `None` if no meals are requested.
+    pub fn meals_of<'a>(&self, location: &APILocation, data: &'a Data) -> Option<Vec<&'a Meal>> {
+        match self.with_meals? {
+            WithMeals::Today => Some(
+                data.find_meals(
+                    Some(std::slice::from_ref(&self.today)),
+                    Some(std::slice::from_ref(&location.code)),
+                    None,
+                )
+                .into_iter()
+                .filter(|meal| {
+                    accepts_language_at(
+                        &self.served_languages,
+                        &self.language,
+                        &meal.location.code,
+                        &meal.language.code,
+                    )
+                })
+                .collect(),
+            ),
+        }
+    }
 }
 
 impl APIFilter<APILocation> for LocationsQuery {
@@ -115,4 +1014,167 @@ impl APIFilter<APILocation> for LocationsQuery {
                 .map(|c| c.contains(&location.city))
                 .unwrap_or(true)
     }
+
+    fn negotiate_languages(&mut self, accept_language: Option<&str>, data: &Data) {
+        self.served_languages = Some(ServedLanguages::negotiate(
+            self.language.as_deref(),
+            accept_language,
+            data.get_languages(),
+            data.get_locations()
+                .iter()
+                .filter(|location| self.accepts(location)),
+        ));
+    }
+
+    fn content_language(&self) -> Option<String> {
+        self.served_languages
+            .as_ref()
+            .map(|served| served.content_language(self.with_meals.is_some()))
+    }
+
+    fn sort(&self) -> Option<&str> {
+        self.sort.as_deref()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiagnosticsQuery {
+    /// Comma separated codes of the scraped locations
+    #[param(value_type = Option<String>)]
+    location: Option<CommaSeparated>,
+    /// Comma separated weeks, 0 being the current week
+    #[param(value_type = Option<String>)]
+    week: Option<CommaSeparated>,
+    /// Comma separated language codes. All languages, if not set
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+    kind: Option<WarningKind>,
+    /// Comma separated sort keys, each optionally suffixed with ':asc' or ':desc'. Available keys: location, week, language, kind
+    sort: Option<String>,
+    /// The maximum number of elements
+    limit: Option<usize>,
+    /// The number of elements to skip
+    offset: Option<usize>,
+}
+
+impl APIFilter<ScrapeWarning> for DiagnosticsQuery {
+    fn accepts(&self, warning: &ScrapeWarning) -> bool {
+        self.location
+            .as_ref()
+            .map(|l| l.contains(&warning.location))
+            .unwrap_or(true)
+            && self
+                .week
+                .as_ref()
+                .map(|w| w.iter().any(|week| week.parse() == Ok(warning.week)))
+                .unwrap_or(true)
+            && self
+                .language
+                .as_ref()
+                .map(|l| l.contains(&warning.language))
+                .unwrap_or(true)
+            && self.kind.map(|k| k == warning.kind).unwrap_or(true)
+    }
+
+    fn sort(&self) -> Option<&str> {
+        self.sort.as_deref()
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use itertools::Itertools;
+
+    #[tokio::test]
+    async fn finds_the_meals_it_would_filter() {
+        let data = fixtures::data("filter-meals").await;
+        let days = fixtures::days(0);
+        // The queries with the Accept-Language header and the number of meals found
+        let queries = [
+            (MealsQuery::default(), None, 6),
+            (MealsQuery::default(), Some("en-US,en;q=0.9"), 6),
+            (MealsQuery::default().with_languages(["en"]), None, 6),
+            (
+                MealsQuery::default().with_languages(["en", "de"]),
+                Some("en"),
+                10,
+            ),
+            (
+                MealsQuery::default()
+                    .with_dates([&days[0], &days[2]])
+                    .with_locations(["HL_ME", "HL_MH"]),
+                None,
+                2,
+            ),
+            (
+                MealsQuery::default()
+                    .with_dates([&days[1]])
+                    .with_locations(["HL_MH", "HL_CA"]),
+                Some("en"),
+                1,
+            ),
+            (
+                MealsQuery::default()
+                    .with_locations(["HL_CA", "HL_ME"])
+                    .with_languages(["de", "en"])
+                    .with_excluded_allergens(["Gl"])
+                    .with_vegan(true),
+                None,
+                2,
+            ),
+            (
+                MealsQuery::default()
+                    .with_dates([&days[0], &days[1], &days[4]])
+                    .with_search("curry"),
+                Some("de"),
+                2,
+            ),
+            (MealsQuery::default().with_dates([&days[4]]), None, 0),
+        ];
+
+        for (mut query, accept_language, count) in queries {
+            query.negotiate_languages(accept_language, &data);
+            let filtered = query.filter(data.get_meals());
+            assert_eq!(filtered.len(), count, "{query:?}");
+            assert_eq!(query.filter_meals(&data), filtered, "{query:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn negotiates_the_languages_of_the_locations() {
+        let data = fixtures::data("negotiate-meals").await;
+        let mut query = MealsQuery::default().with_languages(["en"]);
+        query.negotiate_languages(None, &data);
+        let languages_at = |location: &str| {
+            query
+                .filter_meals(&data)
+                .iter()
+                .filter(|meal| meal.location.code == location)
+                .map(|meal| meal.language.code.as_str())
+                .unique()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(languages_at("HL_ME"), ["en"]);
+        // The Musikhochschule has no English menu, so it falls back to German
+        assert_eq!(languages_at("HL_MH"), ["de"]);
+    }
 }
@@ -0,0 +1,722 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use reqwest::header::{HeaderValue, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Weeks;
+use crate::model::Allergen;
+use crate::refresh::RefreshScope;
+use crate::scrapers::Menu;
+
+/// The name of the directory of a snapshot is the time the scrape started.
+/// In milliseconds, as a refresh of a few unchanged pages takes less than a second.
+const SNAPSHOT_DIR_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+/// Also reads the names of the snapshots archived in seconds
+const SNAPSHOT_DIR_PARSE_FORMAT: &str = "%Y%m%dT%H%M%S%.fZ";
+const INDEX_FILE: &str = "index.json";
+
+/// Where the scraper gets the pages of the studentenwerk.sh website from
+#[derive(Debug, Clone, Default)]
+pub enum PageSource {
+    /// Fetches the pages from the website
+    #[default]
+    Live,
+    /// Fetches the pages from the website and stores every scrape as a snapshot in the archive
+    Archived(Archive),
+    /// Reads the pages from an archived snapshot instead of the website
+    Replay(Snapshot),
+}
+
+/// A directory of snapshots, one for every scrape
+#[derive(Debug, Clone)]
+pub struct Archive {
+    dir: PathBuf,
+    /// Snapshots older than this are deleted
+    retention: Duration,
+    /// The oldest snapshots are deleted once all snapshots together are larger than this many bytes
+    max_size: Option<u64>,
+}
+
+/// The pages of one scrape
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    dir: PathBuf,
+    index: SnapshotIndex,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotIndex {
+    /// The weeks that were scraped, so a replay requests the same pages
+    weeks: Weeks,
+    pages: Vec<ArchivedPage>,
+    /// The error the scrape failed with. The pages fetched until then are archived all the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The scope of a partial refresh. Its pages are replayed on top of the last full scrape before it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scope: Option<RefreshScope>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPage {
+    pub(crate) url: String,
+    pub(crate) fetched_at: DateTime<Utc>,
+    /// The SHA-256 hash of the content
+    pub(crate) sha256: String,
+    /// The file in the snapshot directory holding the content
+    pub(crate) file: String,
+}
+
+impl Archive {
+    pub fn new(dir: impl Into<PathBuf>, retention: Duration) -> Self {
+        Self {
+            dir: dir.into(),
+            retention,
+            max_size: None,
+        }
+    }
+
+    /// Limits the size of all snapshots together. The newest snapshot is kept, even if it is larger.
+    pub fn with_max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Deletes the snapshots older than the retention period and the oldest snapshots beyond the maximum size
+    fn prune(&self) -> anyhow::Result<()> {
+        let oldest = Utc::now() - self.retention;
+        let mut snapshots = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            // Leaves everything alone that is not a snapshot
+            if let Some(created) = snapshot_created(&entry.path()) {
+                snapshots.push((created, entry.path()));
+            }
+        }
+        snapshots.sort_by_key(|(created, _)| std::cmp::Reverse(*created));
+
+        let mut size = 0;
+        for (i, (created, path)) in snapshots.into_iter().enumerate() {
+            size += dir_size(&path)?;
+            let too_large = self.max_size.is_some_and(|max_size| size > max_size);
+            if created < oldest || (i > 0 && too_large) {
+                std::fs::remove_dir_all(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Loads a snapshot directory of the archive
+    pub fn load(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        let index = serde_json::from_str(&std::fs::read_to_string(dir.join(INDEX_FILE))?)?;
+        Ok(Self { dir, index })
+    }
+
+    pub fn get_weeks(&self) -> Weeks {
+        self.index.weeks
+    }
+
+    pub fn get_pages(&self) -> &Vec<ArchivedPage> {
+        &self.index.pages
+    }
+
+    /// The scope of the refresh, if only a part of the pages was scraped
+    pub fn get_scope(&self) -> Option<&RefreshScope> {
+        self.index.scope.as_ref()
+    }
+
+    /// The snapshots to replay one after the other to rebuild the data of this snapshot.
+    /// A full scrape is replayed alone. A partial refresh is replayed after the last successful full scrape
+    /// before it and the successful refreshes in between, which have to be in the same archive directory.
+    pub fn replay_chain(&self) -> anyhow::Result<Vec<Snapshot>> {
+        if self.index.scope.is_none() {
+            return Ok(vec![self.clone()]);
+        }
+        let created = snapshot_created(&self.dir).ok_or(anyhow!(
+            "{} is not a snapshot directory of an archive",
+            self.dir.display()
+        ))?;
+        let archive = self.dir.parent().ok_or(anyhow!(
+            "{} is not in an archive directory",
+            self.dir.display()
+        ))?;
+
+        let mut earlier = vec![];
+        for entry in std::fs::read_dir(archive)? {
+            let path = entry?.path();
+            match snapshot_created(&path) {
+                Some(at) if at < created => {
+                    // Snapshots of running scrapes have no index yet
+                    if let Ok(snapshot) = Snapshot::load(&path) {
+                        earlier.push((at, snapshot));
+                    }
+                }
+                _ => {}
+            }
+        }
+        earlier.sort_by_key(|(at, _)| *at);
+
+        let mut chain = vec![];
+        for (_, snapshot) in earlier.into_iter().rev() {
+            if snapshot.index.error.is_some() {
+                continue;
+            }
+            let full = snapshot.index.scope.is_none();
+            chain.push(snapshot);
+            if full {
+                chain.reverse();
+                chain.push(self.clone());
+                return Ok(chain);
+            }
+        }
+        Err(anyhow!(
+            "No full scrape archived before the refresh {}",
+            self.dir.display()
+        ))
+    }
+
+    fn read(&self, url: &str) -> anyhow::Result<String> {
+        let page = self
+            .index
+            .pages
+            .iter()
+            .find(|page| page.url == url)
+            .ok_or(anyhow!(
+                "{url} is not part of the snapshot {}",
+                self.dir.display()
+            ))?;
+        Ok(std::fs::read_to_string(self.dir.join(&page.file))?)
+    }
+}
+
+/// Remembers the validators of every fetched menu page across scrapes, with the menu parsed from it.
+/// Pages are requested conditionally, and the menus of unchanged pages are not parsed again.
+/// Only the pages of the last full scrape are kept, so pages the website no longer lists are forgotten.
+#[derive(Debug, Default)]
+pub(crate) struct PageCache {
+    http: reqwest::Client,
+    menus: Mutex<HashMap<String, CachedMenu>>,
+}
+
+/// What identifies the content of a page
+#[derive(Debug, Clone)]
+struct Validators {
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    sha256: String,
+}
+
+#[derive(Debug, Clone)]
+struct CachedMenu {
+    validators: Validators,
+    /// The fingerprint of the allergens the menu was parsed with
+    allergens: u64,
+    menu: Menu,
+}
+
+/// A fetched page. Error pages have no validators, as they are parsed like before, but never cached.
+struct Fetched {
+    html: String,
+    validators: Option<Validators>,
+}
+
+/// A fetched menu page
+#[derive(Debug)]
+pub(crate) enum MenuPage {
+    /// The page is the same as the last time, so this is the menu parsed from it then
+    Unchanged(Menu),
+    /// The content of a new or changed page, to be parsed and stored with [`PageFetcher::store_menu`]
+    Changed(String),
+}
+
+/// Fetches the pages of one scrape from the page source.
+/// Archiving is best effort, so a full disk does not stop the API from refreshing.
+/// The snapshot is only complete once [`PageFetcher::finish`] wrote its index.
+#[derive(Debug)]
+pub(crate) struct PageFetcher<'a> {
+    source: &'a PageSource,
+    cache: &'a PageCache,
+    /// The snapshot directory of this scrape, if it is archived. Created with the first archived page.
+    snapshot_dir: Option<PathBuf>,
+    archived: Mutex<HashMap<String, ArchivedPage>>,
+    /// The validators of the changed menu pages, until their menu is stored
+    changed: Mutex<HashMap<String, Validators>>,
+    /// The URLs of the menu pages fetched in this scrape
+    fetched: Mutex<HashSet<String>>,
+}
+
+impl<'a> PageFetcher<'a> {
+    pub(crate) fn new(source: &'a PageSource, cache: &'a PageCache) -> Self {
+        let snapshot_dir = match source {
+            PageSource::Archived(archive) => Some(
+                archive
+                    .dir
+                    .join(Utc::now().format(SNAPSHOT_DIR_FORMAT).to_string()),
+            ),
+            _ => None,
+        };
+
+        Self {
+            source,
+            cache,
+            snapshot_dir,
+            archived: Mutex::new(HashMap::new()),
+            changed: Mutex::new(HashMap::new()),
+            fetched: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub(crate) async fn get(&self, url: &str) -> anyhow::Result<String> {
+        match self.fetch(url, None).await? {
+            Some(page) => Ok(page.html),
+            None => Err(anyhow!(
+                "{url} was not modified, but was requested unconditionally"
+            )),
+        }
+    }
+
+    /// Fetches the menu page, unless the website reports that it has not changed since its menu was parsed
+    pub(crate) async fn get_menu_page(
+        &self,
+        url: &str,
+        allergens: &[Allergen],
+    ) -> anyhow::Result<MenuPage> {
+        if let Ok(mut fetched) = self.fetched.lock() {
+            fetched.insert(url.to_string());
+        }
+        let cached = match self.source {
+            PageSource::Replay(_) => None,
+            _ => self.cache.get(url, allergens_fingerprint(allergens)),
+        };
+        // An archived scrape stores every page, so it needs the content even if the page did not change
+        let validators = cached
+            .as_ref()
+            .filter(|_| matches!(self.source, PageSource::Live))
+            .map(|cached| &cached.validators);
+
+        match (self.fetch(url, validators).await?, cached) {
+            (None, Some(cached)) => Ok(MenuPage::Unchanged(cached.menu)),
+            (None, None) => Err(anyhow!("{url} was not modified, but nothing is cached")),
+            (Some(page), cached) => {
+                let Some(validators) = page.validators else {
+                    return Ok(MenuPage::Changed(page.html));
+                };
+                match cached {
+                    // Many servers send no validators, so the content decides as well
+                    Some(cached) if cached.validators.sha256 == validators.sha256 => {
+                        let menu = cached.menu.clone();
+                        self.cache.insert(
+                            url,
+                            CachedMenu {
+                                validators,
+                                ..cached
+                            },
+                        );
+                        Ok(MenuPage::Unchanged(menu))
+                    }
+                    _ => {
+                        if let Ok(mut changed) = self.changed.lock() {
+                            changed.insert(url.to_string(), validators);
+                        }
+                        Ok(MenuPage::Changed(page.html))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remembers the menu parsed from a changed page, so it is not parsed again while the page stays the same
+    pub(crate) fn store_menu(&self, url: &str, allergens: &[Allergen], menu: &Menu) {
+        let validators = self
+            .changed
+            .lock()
+            .ok()
+            .and_then(|mut changed| changed.remove(url));
+        if let Some(validators) = validators {
+            self.cache.insert(
+                url,
+                CachedMenu {
+                    validators,
+                    allergens: allergens_fingerprint(allergens),
+                    menu: menu.clone(),
+                },
+            );
+        }
+    }
+
+    /// Requests the page, conditionally if validators are given. `None` if it was not modified.
+    async fn fetch(
+        &self,
+        url: &str,
+        validators: Option<&Validators>,
+    ) -> anyhow::Result<Option<Fetched>> {
+        if let PageSource::Replay(snapshot) = self.source {
+            return Ok(Some(Fetched {
+                html: snapshot.read(url)?,
+                validators: None,
+            }));
+        }
+
+        let mut request = self.cache.http.get(url);
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+        if validators.is_some() && response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(None);
+        }
+
+        let success = response.status().is_success();
+        let etag = response.headers().get(ETAG).cloned();
+        let last_modified = response.headers().get(LAST_MODIFIED).cloned();
+        let html = response.text().await?;
+        if let Some(dir) = &self.snapshot_dir {
+            if let Err(e) = self.archive(dir, url, &html).await {
+                eprintln!("Failed to archive {url}: {e}");
+            }
+        }
+        let validators = success.then(|| Validators {
+            etag,
+            last_modified,
+            sha256: sha256(&html),
+        });
+        Ok(Some(Fetched { html, validators }))
+    }
+
+    async fn archive(&self, dir: &Path, url: &str, html: &str) -> anyhow::Result<()> {
+        let sha256 = sha256(html);
+        // Identical pages, e.g. the allergen page of every language, are stored once
+        let file = format!("{sha256}.html");
+        tokio::fs::create_dir_all(dir).await?;
+        tokio::fs::write(dir.join(&file), html).await?;
+
+        self.archived
+            .lock()
+            .map_err(|_| anyhow!("Archive lock poisoned"))?
+            .insert(
+                url.to_string(),
+                ArchivedPage {
+                    url: url.to_string(),
+                    fetched_at: Utc::now(),
+                    sha256,
+                    file,
+                },
+            );
+        Ok(())
+    }
+
+    /// Ends a scrape, which is full without a scope. If a full scrape succeeded, the cached pages it did not fetch are forgotten.
+    /// Writes the index of the snapshot and deletes the snapshots beyond the retention period.
+    /// Called for failed scrapes as well, so their pages can be replayed.
+    pub(crate) async fn finish(
+        self,
+        weeks: Weeks,
+        scope: Option<&RefreshScope>,
+        error: Option<&anyhow::Error>,
+    ) {
+        if scope.is_none() && error.is_none() && !matches!(self.source, PageSource::Replay(_)) {
+            if let Ok(fetched) = self.fetched.lock() {
+                self.cache.retain(&fetched);
+            }
+        }
+        let (Some(dir), PageSource::Archived(archive)) = (self.snapshot_dir, self.source) else {
+            return;
+        };
+        let mut pages: Vec<ArchivedPage> = self
+            .archived
+            .into_inner()
+            .unwrap_or_default()
+            .into_values()
+            .collect();
+        pages.sort_by(|a, b| a.url.cmp(&b.url));
+
+        let index = SnapshotIndex {
+            weeks,
+            pages,
+            error: error.map(|e| e.to_string()),
+            scope: scope.cloned(),
+        };
+        let archive = archive.clone();
+        let result = async {
+            let json = serde_json::to_string_pretty(&index)?;
+            tokio::fs::create_dir_all(&dir).await?;
+            tokio::fs::write(dir.join(INDEX_FILE), json).await?;
+            tokio::task::spawn_blocking(move || archive.prune()).await?
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("Failed to finish snapshot {}: {e}", dir.display());
+        }
+    }
+}
+
+impl PageCache {
+    /// The cached menu of the page, if it was parsed with the allergens of the fingerprint
+    fn get(&self, url: &str, allergens: u64) -> Option<CachedMenu> {
+        self.menus
+            .lock()
+            .ok()?
+            .get(url)
+            .filter(|cached| cached.allergens == allergens)
+            .cloned()
+    }
+
+    fn insert(&self, url: &str, menu: CachedMenu) {
+        if let Ok(mut menus) = self.menus.lock() {
+            menus.insert(url.to_string(), menu);
+        }
+    }
+
+    fn retain(&self, urls: &HashSet<String>) {
+        if let Ok(mut menus) = self.menus.lock() {
+            menus.retain(|url, _| urls.contains(url));
+        }
+    }
+}
+
+/// The time the snapshot in the directory was created, `None` if it is no snapshot
+fn snapshot_created(dir: &Path) -> Option<DateTime<Utc>> {
+    let name = dir.file_name()?.to_str()?;
+    NaiveDateTime::parse_from_str(name, SNAPSHOT_DIR_PARSE_FORMAT)
+        .ok()
+        .map(|created| created.and_utc())
+}
+
+fn sha256(html: &str) -> String {
+    Sha256::digest(html.as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// The size of the files in the directory
+fn dir_size(dir: &Path) -> anyhow::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(dir)? {
+        size += entry?.metadata()?.len();
+    }
+    Ok(size)
+}
+
+fn allergens_fingerprint(allergens: &[Allergen]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    allergens.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mensa-api-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn create_snapshot(archive: &Path, age: Duration, bytes: usize) -> PathBuf {
+        let dir = archive.join((Utc::now() - age).format(SNAPSHOT_DIR_FORMAT).to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("page.html"), "x".repeat(bytes)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn prunes_old_snapshots_and_the_oldest_beyond_the_size() {
+        let dir = temp_dir("prune");
+        let newest = create_snapshot(&dir, Duration::hours(1), 100);
+        let older = create_snapshot(&dir, Duration::hours(2), 100);
+        let expired = create_snapshot(&dir, Duration::days(30), 10);
+        let other = dir.join("notes");
+        std::fs::create_dir_all(&other).unwrap();
+
+        Archive::new(&dir, Duration::days(14))
+            .with_max_size(150)
+            .prune()
+            .unwrap();
+
+        assert!(newest.exists());
+        assert!(!older.exists());
+        assert!(!expired.exists());
+        assert!(other.exists());
+
+        // The newest snapshot is kept, even if it alone is too large
+        Archive::new(&dir, Duration::days(14))
+            .with_max_size(50)
+            .prune()
+            .unwrap();
+        assert!(newest.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Serves a menu page with an ETag, answering 304 to a matching request, and a page without validators.
+    /// Counts the pages sent in full.
+    async fn serve_pages() -> (String, Arc<AtomicUsize>) {
+        use warp::Filter;
+
+        let sent = Arc::new(AtomicUsize::new(0));
+        let etag_sent = sent.clone();
+        let with_etag = warp::path("etag")
+            .and(warp::header::optional::<String>("if-none-match"))
+            .map(move |etag: Option<String>| {
+                if etag.as_deref() == Some("\"v1\"") {
+                    return warp::http::Response::builder()
+                        .status(304)
+                        .body(String::new())
+                        .unwrap();
+                }
+                etag_sent.fetch_add(1, Ordering::SeqCst);
+                warp::http::Response::builder()
+                    .header("etag", "\"v1\"")
+                    .body("<p>Menu</p>".to_string())
+                    .unwrap()
+            });
+        let plain_sent = sent.clone();
+        let without_validators = warp::path("plain").map(move || {
+            plain_sent.fetch_add(1, Ordering::SeqCst);
+            "<p>Menu</p>"
+        });
+        let (address, server) =
+            warp::serve(with_etag.or(without_validators)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (format!("http://{address}"), sent)
+    }
+
+    fn menu(day: &str) -> Menu {
+        Menu {
+            days: vec![day.to_string()],
+            meals: vec![],
+            warnings: vec![],
+            slices: vec![],
+        }
+    }
+
+    /// The days of the menu, or `None` if the page changed
+    async fn unchanged_menu(pages: &PageFetcher<'_>, url: &str) -> Option<Vec<String>> {
+        match pages.get_menu_page(url, &[]).await.unwrap() {
+            MenuPage::Unchanged(menu) => Some(menu.days),
+            MenuPage::Changed(html) => {
+                assert_eq!(html, "<p>Menu</p>");
+                pages.store_menu(url, &[], &menu("2024-10-14"));
+                None
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn reuses_the_menus_of_unchanged_pages() {
+        let (base, sent) = serve_pages().await;
+        let (etag_url, plain_url) = (format!("{base}/etag"), format!("{base}/plain"));
+        let source = PageSource::Live;
+        let cache = PageCache::default();
+        let pages = PageFetcher::new(&source, &cache);
+
+        assert_eq!(unchanged_menu(&pages, &etag_url).await, None);
+        assert_eq!(unchanged_menu(&pages, &plain_url).await, None);
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+        // Not modified according to the ETag, so the page is not sent again
+        let days = Some(vec!["2024-10-14".to_string()]);
+        assert_eq!(unchanged_menu(&pages, &etag_url).await, days);
+        assert_eq!(sent.load(Ordering::SeqCst), 2);
+        // Sent again, but with the same content
+        assert_eq!(unchanged_menu(&pages, &plain_url).await, days);
+        assert_eq!(sent.load(Ordering::SeqCst), 3);
+
+        // Menus parsed with other allergens are parsed again
+        let allergens = [Allergen {
+            code: "Gl".into(),
+            name: "Gluten".into(),
+            language: Default::default(),
+        }];
+        assert!(matches!(
+            pages.get_menu_page(&etag_url, &allergens).await.unwrap(),
+            MenuPage::Changed(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn forgets_the_pages_a_full_scrape_did_not_fetch() {
+        let (base, _) = serve_pages().await;
+        let (etag_url, plain_url) = (format!("{base}/etag"), format!("{base}/plain"));
+        let source = PageSource::Live;
+        let cache = PageCache::default();
+
+        let pages = PageFetcher::new(&source, &cache);
+        unchanged_menu(&pages, &etag_url).await;
+        unchanged_menu(&pages, &plain_url).await;
+        pages.finish(Weeks::Fixed(1), None, None).await;
+
+        let pages = PageFetcher::new(&source, &cache);
+        assert!(unchanged_menu(&pages, &etag_url).await.is_some());
+        pages.finish(Weeks::Fixed(1), None, None).await;
+
+        let pages = PageFetcher::new(&source, &cache);
+        assert!(unchanged_menu(&pages, &etag_url).await.is_some());
+        assert_eq!(unchanged_menu(&pages, &plain_url).await, None);
+    }
+
+    #[tokio::test]
+    async fn writes_the_scope_of_refreshes() {
+        let dir = temp_dir("refresh-scope");
+        let source = PageSource::Archived(Archive::new(&dir, Duration::days(14)));
+        let cache = PageCache::default();
+        let scope = RefreshScope::new(vec!["HL_ME".into()], vec![1], vec![]);
+
+        PageFetcher::new(&source, &cache)
+            .finish(Weeks::Fixed(2), Some(&scope), None)
+            .await;
+
+        let snapshot_dir = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let snapshot = Snapshot::load(snapshot_dir.path()).unwrap();
+        assert_eq!(snapshot.get_weeks(), Weeks::Fixed(2));
+        assert_eq!(snapshot.get_scope(), Some(&scope));
+        // Nothing to replay the refresh on top of
+        assert!(snapshot.replay_chain().is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_the_index_of_failed_scrapes() {
+        let dir = temp_dir("failed-scrape");
+        let source = PageSource::Archived(Archive::new(&dir, Duration::days(14)));
+        let cache = PageCache::default();
+
+        PageFetcher::new(&source, &cache)
+            .finish(
+                Weeks::Fixed(1),
+                None,
+                Some(&anyhow!("Failed to get the allergen parent element")),
+            )
+            .await;
+
+        let snapshot_dir = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+        let snapshot = Snapshot::load(snapshot_dir.path()).unwrap();
+        assert_eq!(snapshot.get_weeks(), Weeks::Fixed(1));
+        assert!(snapshot.get_pages().is_empty());
+        assert_eq!(
+            snapshot.index.error.as_deref(),
+            Some("Failed to get the allergen parent element")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,56 +1,159 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
 use anyhow::anyhow;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use chrono::{DateTime, Duration, Utc};
+use serde_json::value::{to_raw_value, RawValue};
 
-use crate::model::Data;
+use crate::history::History;
+use crate::model::{Data, MealView};
+use crate::scrapers::Scraper;
 
-#[derive(Debug, Clone)]
+/// Holds the served data without locks.
+/// A refresh builds new immutable data and swaps it in at once, so requests never wait for a refresh.
+#[derive(Debug)]
 pub struct Cache {
-    data: Option<Data>,
-    last_updated: DateTime<Utc>,
+    data: ArcSwapOption<CachedData>,
+    history: ArcSwap<History>,
     ttl: Duration,
 }
 
+/// The data served since one refresh, with the JSON of its meals and allergens serialized in advance
+#[derive(Debug)]
+pub struct CachedData {
+    data: Data,
+    /// The JSON of every meal without a category price, in the order of the meals
+    meals_json: Vec<Box<RawValue>>,
+    /// The JSON of every allergen, in the order of the allergens
+    allergens_json: Vec<Box<RawValue>>,
+    last_updated: DateTime<Utc>,
+}
+
 impl Cache {
-    pub async fn get_data(&self) -> anyhow::Result<&Data> {
-        self.data.as_ref().ok_or(anyhow!(
+    pub fn get_data(&self) -> anyhow::Result<Arc<CachedData>> {
+        self.data.load_full().ok_or(anyhow!(
             "Failed to get data, because option is empty. This should not have happened!"
         ))
     }
 
     pub fn needs_update(&self) -> bool {
         let now = chrono::offset::Utc::now();
-        self.last_updated + self.ttl < now
+        self.get_last_updated() + self.ttl < now
     }
 
     #[deprecated]
-    pub async fn fetch(&mut self) -> anyhow::Result<()> {
-        self.data = Some(Data::fetch().await?);
-        self.last_updated = chrono::offset::Utc::now();
-        Ok(())
+    pub async fn fetch(&self, scraper: &Scraper) -> anyhow::Result<()> {
+        self.set_data(Data::fetch(scraper).await?)
     }
 
-    pub async fn fetch_data() -> anyhow::Result<Data> {
-        Data::fetch().await
+    pub async fn fetch_data(scraper: &Scraper) -> anyhow::Result<Data> {
+        Data::fetch(scraper).await
     }
 
-    pub fn set_data(&mut self, data: Data) {
-        if self.data.is_none() {
+    /// Serializes the data and replaces the served data with it
+    pub fn set_data(&self, data: Data) -> anyhow::Result<()> {
+        let data = CachedData::new(data)?;
+        // The refreshes are the only writers, so the history is not changed in between
+        let mut history = History::clone(&self.history.load());
+        history.record(data.get_meals());
+        self.history.store(Arc::new(history));
+        if self.data.swap(Some(Arc::new(data))).is_none() {
             println!("Cache ready...");
         }
-        self.data = Some(data);
-        self.last_updated = chrono::offset::Utc::now();
+        Ok(())
+    }
+
+    pub fn get_history(&self) -> Arc<History> {
+        self.history.load_full()
+    }
+
+    pub fn set_history(&self, history: History) {
+        self.history.store(Arc::new(history));
     }
 
     pub fn new(ttl: Duration) -> anyhow::Result<Self> {
         println!("Cache initialized with ttl of {ttl}");
         Ok(Self {
-            data: None,
-            last_updated: DateTime::from_timestamp_nanos(0),
+            data: ArcSwapOption::empty(),
+            history: ArcSwap::default(),
             ttl,
         })
     }
 
     pub fn get_last_update_as_string(&self) -> String {
-        self.last_updated.to_string()
+        self.get_last_updated().to_string()
+    }
+
+    fn get_last_updated(&self) -> DateTime<Utc> {
+        self.data
+            .load()
+            .as_ref()
+            .map(|data| data.last_updated)
+            .unwrap_or(DateTime::from_timestamp_nanos(0))
+    }
+}
+
+impl CachedData {
+    fn new(data: Data) -> anyhow::Result<Self> {
+        let meals_json = data
+            .get_meals()
+            .iter()
+            .map(|meal| to_raw_value(&MealView::new(meal, None)))
+            .collect::<Result<_, _>>()?;
+        let allergens_json = data
+            .get_allergens()
+            .iter()
+            .map(to_raw_value)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            data,
+            meals_json,
+            allergens_json,
+            last_updated: chrono::offset::Utc::now(),
+        })
+    }
+
+    pub fn get_data(&self) -> &Data {
+        &self.data
+    }
+
+    /// The JSON of the meal at the position in the meals of the data, without a category price
+    pub fn meal_json(&self, position: usize) -> Option<&RawValue> {
+        self.meals_json.get(position).map(AsRef::as_ref)
+    }
+
+    /// The JSON of the allergen at the position in the allergens of the data
+    pub fn allergen_json(&self, position: usize) -> Option<&RawValue> {
+        self.allergens_json.get(position).map(AsRef::as_ref)
+    }
+}
+
+impl Deref for CachedData {
+    type Target = Data;
+
+    fn deref(&self) -> &Data {
+        &self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[tokio::test]
+    async fn serializes_the_meals_and_allergens_at_their_positions() {
+        let data = CachedData::new(fixtures::data("cached-json").await).unwrap();
+        assert!(!data.get_meals().is_empty());
+        for (i, meal) in data.get_meals().iter().enumerate() {
+            let json = to_raw_value(&MealView::new(meal, None)).unwrap();
+            assert_eq!(data.meal_json(i).unwrap().get(), json.get());
+        }
+        for (i, allergen) in data.get_allergens().iter().enumerate() {
+            let json = to_raw_value(allergen).unwrap();
+            assert_eq!(data.allergen_json(i).unwrap().get(), json.get());
+        }
+        assert!(data.meal_json(data.get_meals().len()).is_none());
     }
 }
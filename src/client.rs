@@ -0,0 +1,100 @@
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::api_filter::{AllergensQuery, LocationsQuery, MealsQuery};
+use crate::model::{APILocation, Allergen, Meal};
+
+/// The API operated by the maintainers of this crate
+pub const DEFAULT_BASE_URL: &str = "https://speiseplan.mcloud.digital";
+
+/// A typed client for the v2 REST API
+#[derive(Debug, Clone)]
+pub struct MensaClient {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+/// A page of elements as returned by the API
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClientResponse<T> {
+    pub last_updated: String,
+    /// The number of elements matching the query before pagination
+    pub total: usize,
+    pub data: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+impl Default for MensaClient {
+    fn default() -> Self {
+        Self::new(DEFAULT_BASE_URL)
+    }
+}
+
+impl MensaClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Fetches the meals. Grouped responses are not supported, so the query must not group the meals.
+    pub async fn meals(&self, query: &MealsQuery) -> anyhow::Result<ClientResponse<Meal>> {
+        if query.group_by().is_some() {
+            return Err(anyhow!("Grouped meals are not supported by the client"));
+        }
+        let mut response: ClientResponse<Meal> = self.get("/v2/meals", query).await?;
+        // The allergens nested into a meal are in the language of the meal
+        for meal in &mut response.data {
+            for allergen in &mut meal.allergens {
+                allergen.language = meal.language.clone();
+            }
+        }
+        Ok(response)
+    }
+
+    pub async fn allergens(
+        &self,
+        query: &AllergensQuery,
+    ) -> anyhow::Result<ClientResponse<Allergen>> {
+        self.get("/v2/allergens", query).await
+    }
+
+    /// Fetches the locations. Embedded meals are not part of the response.
+    pub async fn locations(
+        &self,
+        query: &LocationsQuery,
+    ) -> anyhow::Result<ClientResponse<APILocation>> {
+        self.get("/v2/locations", query).await
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &impl Serialize,
+    ) -> anyhow::Result<T> {
+        let response = self
+            .http
+            .get(format!("{}{path}", self.base_url))
+            .query(query)
+            .send()
+            .await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response
+                .json::<ErrorResponse>()
+                .await
+                .map(|error| error.message)
+                .unwrap_or_default();
+            return Err(anyhow!("Request to {path} failed with {status}: {message}"));
+        }
+
+        Ok(response.json().await?)
+    }
+}
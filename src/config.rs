@@ -1,4 +1,8 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
 use envconfig::Envconfig;
+use serde::{Deserialize, Serialize};
 
 #[derive(Envconfig)]
 pub struct Config {
@@ -9,4 +13,92 @@ pub struct Config {
     /// The Time-To-Live for the cache after which it will be refreshed in seconds. Default: 2700
     #[envconfig(from = "TTL", default = "2700")]
     pub ttl: u32,
+
+    /// The TOML file with the times the data is refreshed at. The data is refreshed every `TTL` seconds, if not set.
+    #[envconfig(from = "SCHEDULE_FILE")]
+    pub schedule_file: Option<String>,
+
+    /// The JSON file the history of served meals is persisted to. The history is kept in memory only, if not set.
+    #[envconfig(from = "HISTORY_FILE")]
+    pub history_file: Option<String>,
+
+    /// How many days of served meals the history keeps. Default: 730
+    #[envconfig(from = "HISTORY_RETENTION_DAYS", default = "730")]
+    pub history_retention_days: u32,
+
+    /// The TOML file the locations are loaded from. The locations shipped with the API are used, if not set.
+    #[envconfig(from = "LOCATIONS_FILE")]
+    pub locations_file: Option<String>,
+
+    /// The TOML file the languages are loaded from. The languages shipped with the API are used, if not set.
+    #[envconfig(from = "LANGUAGES_FILE")]
+    pub languages_file: Option<String>,
+
+    /// How many weeks of menus are scraped, starting with the current week.
+    /// `auto` scrapes until the website has no menu for a week. Default: 2
+    #[envconfig(from = "WEEKS", default = "2")]
+    pub weeks: Weeks,
+
+    /// The token that grants access to the admin endpoints. The admin endpoints are disabled, if not set.
+    #[envconfig(from = "ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
+
+    /// Serves GraphiQL at `/v2/graphql` and allows introspection of the GraphQL schema. Default: true
+    #[envconfig(from = "GRAPHIQL", default = "true")]
+    pub graphiql: bool,
+
+    /// The directory every scrape is archived to as a snapshot of the fetched pages. Nothing is archived, if not set.
+    #[envconfig(from = "ARCHIVE_DIR")]
+    pub archive_dir: Option<String>,
+
+    /// How many days the snapshots are kept in the archive. Default: 14
+    #[envconfig(from = "ARCHIVE_RETENTION_DAYS", default = "14")]
+    pub archive_retention_days: u32,
+
+    /// How many megabytes all snapshots in the archive may take up. The oldest snapshots are deleted beyond. Default: 1024
+    #[envconfig(from = "ARCHIVE_MAX_SIZE_MB", default = "1024")]
+    pub archive_max_size_mb: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Weeks {
+    /// Scrape a fixed number of weeks
+    Fixed(usize),
+    /// Scrape until a week without menu is found, but at most [`Weeks::MAX_AUTO`] weeks
+    Auto,
+    /// Scrape only the given week, 0 being the current week
+    Single(usize),
+}
+
+impl Weeks {
+    pub const MAX_AUTO: usize = 8;
+
+    /// Whether a scrape of these weeks requests the week, 0 being the current week.
+    /// Automatic scrapes stop at the first week without a menu, as the later weeks have no menu either.
+    pub fn includes(&self, week: usize) -> bool {
+        match self {
+            Weeks::Fixed(weeks) => week < *weeks,
+            Weeks::Auto => week < Self::MAX_AUTO,
+            Weeks::Single(single) => week == *single,
+        }
+    }
+}
+
+impl Default for Weeks {
+    fn default() -> Self {
+        Weeks::Fixed(2)
+    }
+}
+
+impl FromStr for Weeks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Weeks::Auto),
+            weeks => weeks.parse().map(Weeks::Fixed).map_err(|_| {
+                anyhow!("Invalid number of weeks '{weeks}'. Expected a number or 'auto'")
+            }),
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The maximum number of characters of the HTML kept with a warning
+#[cfg(feature = "server")]
+const MAX_SNIPPET_LENGTH: usize = 1000;
+
+/// A problem found while parsing a menu page.
+/// The affected meal is skipped or parsed with defaults instead of failing the whole page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ScrapeWarning {
+    /// The code of the location whose page was scraped
+    pub(crate) location: String,
+    /// The week of the page, 0 being the current week at the time of the scrape
+    pub(crate) week: usize,
+    /// The monday of the week of the page
+    #[serde(default)]
+    pub(crate) week_start: NaiveDate,
+    pub(crate) language: String,
+    pub(crate) kind: WarningKind,
+    pub(crate) message: String,
+    /// The HTML of the offending element, shortened if too long
+    pub(crate) html: Option<String>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// The meal was skipped
+    MissingName,
+    /// The prices of the meal were set to 0
+    UnparsablePrice,
+    /// The meal has no allergens
+    MissingAllergens,
+    /// The allergen code is not listed on the allergen page and was ignored
+    UnknownAllergen,
+    /// The meals of the day were skipped
+    MissingDate,
+    /// The meal of a page shared by two locations could not be assigned to either and was skipped
+    UnknownMenuLocation,
+}
+
+impl ScrapeWarning {
+    pub fn get_location(&self) -> &str {
+        &self.location
+    }
+
+    pub fn get_week(&self) -> usize {
+        self.week
+    }
+
+    pub fn get_week_start(&self) -> NaiveDate {
+        self.week_start
+    }
+
+    pub fn get_language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn get_kind(&self) -> WarningKind {
+        self.kind
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn get_html(&self) -> Option<&str> {
+        self.html.as_deref()
+    }
+}
+
+/// Collects the warnings of one scraped page
+#[cfg(feature = "server")]
+#[derive(Debug)]
+pub(crate) struct PageWarnings<'a> {
+    location: &'a str,
+    week: usize,
+    language: &'a str,
+    warnings: Vec<ScrapeWarning>,
+}
+
+#[cfg(feature = "server")]
+impl<'a> PageWarnings<'a> {
+    pub(crate) fn new(location: &'a str, week: usize, language: &'a str) -> Self {
+        Self {
+            location,
+            week,
+            language,
+            warnings: vec![],
+        }
+    }
+
+    pub(crate) fn push(
+        &mut self,
+        kind: WarningKind,
+        message: impl Into<String>,
+        html: Option<String>,
+    ) {
+        self.warnings.push(ScrapeWarning {
+            location: self.location.to_string(),
+            week: self.week,
+            week_start: crate::model::week_start(self.week),
+            language: self.language.to_string(),
+            kind,
+            message: message.into(),
+            html: html.map(|html| match html.char_indices().nth(MAX_SNIPPET_LENGTH) {
+                Some((end, _)) => format!("{}…", &html[..end]),
+                None => html,
+            }),
+        });
+    }
+
+    pub(crate) fn into_warnings(self) -> Vec<ScrapeWarning> {
+        self.warnings
+    }
+}
@@ -0,0 +1,256 @@
+//! Snapshots of small menu pages, so tests scrape real HTML without the website.
+//! Shared by the tests of the library and the binary through the `fixtures` feature.
+
+use std::path::Path;
+
+use chrono::{Days, Utc};
+
+use crate::archive::{PageSource, Snapshot};
+use crate::config::Weeks;
+use crate::languages::Languages;
+use crate::locations::Locations;
+use crate::model::{week_start, Data};
+use crate::refresh::RefreshScope;
+use crate::scrapers::Scraper;
+
+/// A meal on a menu page
+pub struct FixtureMeal<'a> {
+    pub date: &'a str,
+    pub name: &'a str,
+    /// The menu type, which tells the canteen and the cafeteria sharing a page apart
+    pub art: &'a str,
+    /// The comma separated allergen codes
+    pub allergens: &'a str,
+    /// `vn` for vegan and `ve` for vegetarian meals
+    pub arten: &'a str,
+    pub price: &'a str,
+}
+
+/// The Musikhochschule has a page of its own and opening hours, the canteen and the cafeteria share one
+pub fn scraper() -> Scraper {
+    let locations = Locations::from_toml(
+        r#"
+        [[location]]
+        code = "HL_MH"
+        name = "Musikhochschule"
+        city = "Lübeck"
+        ort = 3
+        mensa = 9
+        coordinates = { lat = 53.8652, lon = 10.6822 }
+
+        [[location.opening_hours.regular]]
+        days = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"]
+        open = "00:00"
+        close = "23:59"
+
+        [[location]]
+        code = "HL_CA"
+        name = "Cafeteria"
+        city = "Lübeck"
+        ort = 3
+        mensa = 8
+        siblings = { mensa = "HL_ME", cafeteria = "HL_CA" }
+
+        [[location]]
+        code = "HL_ME"
+        name = "Mensa"
+        city = "Lübeck"
+        ort = 3
+        mensa = 8
+        siblings = { mensa = "HL_ME", cafeteria = "HL_CA" }
+        "#,
+    )
+    .unwrap();
+    let languages = Languages::from_toml(
+        r#"
+        [[language]]
+        code = "de"
+        name = "Deutsch"
+        menu_slug = "essen"
+        allergen_slug = "allergene"
+
+        [[language]]
+        code = "en"
+        name = "English"
+        menu_slug = "food"
+        allergen_slug = "allergens"
+        fallback = ["de"]
+        "#,
+    )
+    .unwrap();
+    Scraper::new(locations, languages)
+}
+
+/// The monday to friday of the week, 0 being the current week
+pub fn days(week: usize) -> Vec<String> {
+    (0..5)
+        .map(|day| (week_start(week) + Days::new(day)).to_string())
+        .collect()
+}
+
+pub fn allergen_url(language: &str) -> String {
+    let slug = match language {
+        "de" => "allergene",
+        _ => "allergens",
+    };
+    format!("https://studentenwerk.sh/{language}/{slug}?ort=3&mensa=8&nw=0#mensaplan")
+}
+
+pub fn menu_url(language: &str, mensa: usize, week: usize) -> String {
+    let slug = match language {
+        "de" => "essen",
+        _ => "food",
+    };
+    format!("https://studentenwerk.sh/{language}/{slug}?ort=3&mensa={mensa}&nw={week}#mensaplan")
+}
+
+pub fn allergen_page(gluten: &str) -> String {
+    format!(
+        r#"<div class="mbf_content">
+            <div data-wert="Gl"><span>Gl</span><span>{gluten}</span></div>
+            <div data-wert="La"><span>La</span><span>Laktose</span></div>
+        </div>"#
+    )
+}
+
+/// A menu page listing the days, with the meals under their day
+pub fn menu_page(days: &[&str], meals: &[FixtureMeal]) -> String {
+    days.iter()
+        .map(|day| {
+            let meals: String = meals
+                .iter()
+                .filter(|meal| meal.date == *day)
+                .map(|meal| {
+                    format!(
+                        r#"<div class="mensa_menu_detail" data-arten="{}" data-allergene="{}">
+                            <div class="menu_art">{}</div>
+                            <div class="menu_name">{}</div>
+                            <div class="menu_preis">{}</div>
+                        </div>"#,
+                        meal.arten, meal.allergens, meal.art, meal.name, meal.price
+                    )
+                })
+                .collect();
+            format!(r#"<div class="tag_headline" data-day="{day}">{meals}</div>"#)
+        })
+        .collect()
+}
+
+/// Writes the pages as a snapshot into a directory named after the test
+pub fn snapshot(name: &str, weeks: Weeks, pages: &[(String, String)]) -> Snapshot {
+    let dir = std::env::temp_dir().join(format!("mensa-api-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    snapshot_in(&dir, weeks, None, pages)
+}
+
+/// Writes the pages as a snapshot into the directory, as a partial refresh if there is a scope
+pub fn snapshot_in(
+    dir: &Path,
+    weeks: Weeks,
+    scope: Option<&RefreshScope>,
+    pages: &[(String, String)],
+) -> Snapshot {
+    std::fs::create_dir_all(dir).unwrap();
+    let index: Vec<_> = pages
+        .iter()
+        .enumerate()
+        .map(|(i, (url, html))| {
+            std::fs::write(dir.join(format!("{i}.html")), html).unwrap();
+            serde_json::json!({
+                "url": url,
+                "fetched_at": Utc::now(),
+                "sha256": "",
+                "file": format!("{i}.html"),
+            })
+        })
+        .collect();
+    std::fs::write(
+        dir.join("index.json"),
+        serde_json::json!({ "weeks": weeks, "pages": index, "scope": scope }).to_string(),
+    )
+    .unwrap();
+    Snapshot::load(dir).unwrap()
+}
+
+/// Scrapes a snapshot of the current week: the Musikhochschule lists meals on monday and tuesday in German only,
+/// the canteen and the cafeteria list meals on monday to wednesday in German and English
+pub async fn data(name: &str) -> Data {
+    let days = days(0);
+    let days: Vec<&str> = days.iter().map(String::as_str).collect();
+    let meal = |date, name, art, allergens, arten, price| FixtureMeal {
+        date,
+        name,
+        art,
+        allergens,
+        arten,
+        price,
+    };
+    let shared = |mensa, cafeteria| {
+        menu_page(
+            &days[..3],
+            &[
+                meal(
+                    days[0],
+                    "Currywurst",
+                    cafeteria,
+                    "La",
+                    "",
+                    "3,20 € / 4,20 € / 5,20 €",
+                ),
+                meal(
+                    days[0],
+                    "Gemüsecurry",
+                    mensa,
+                    "",
+                    "vn",
+                    "2,90 € / 3,90 € / 4,90 €",
+                ),
+                meal(
+                    days[1],
+                    "Käsespätzle",
+                    mensa,
+                    "Gl,La",
+                    "ve",
+                    "3,50 € / 4,50 € / 5,50 €",
+                ),
+                meal(
+                    days[2],
+                    "Falafel",
+                    cafeteria,
+                    "Gl",
+                    "vn",
+                    "4,10 € / 5,10 € / 6,10 €",
+                ),
+            ],
+        )
+    };
+    let pages = [
+        (allergen_url("de"), allergen_page("Gluten")),
+        (allergen_url("en"), allergen_page("Gluten")),
+        (
+            menu_url("de", 9, 0),
+            menu_page(
+                &days[..2],
+                &[
+                    meal(
+                        days[0],
+                        "Linsen-Dal",
+                        "",
+                        "",
+                        "vn",
+                        "2,50 € / 3,50 € / 4,50 €",
+                    ),
+                    meal(days[1], "Chili", "", "Gl", "", "3,00 € / 4,00 € / 5,00 €"),
+                ],
+            ),
+        ),
+        (menu_url("en", 9, 0), String::new()),
+        (menu_url("de", 8, 0), shared("Mensa", "Cafeteria")),
+        (menu_url("en", 8, 0), shared("Canteen", "Cafeteria")),
+    ];
+    scraper()
+        .with_source(PageSource::Replay(snapshot(name, Weeks::Fixed(1), &pages)))
+        .fetch()
+        .await
+        .unwrap()
+}
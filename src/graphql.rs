@@ -0,0 +1,364 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::api_filter::{APIFilter, AllergensQuery, LocationsQuery, MealsQuery};
+use crate::cache::Cache;
+use crate::model::{APILocation, Allergen, Data, Language, Meal, PriceCategory, Prices};
+use crate::opening_hours::{OpeningHours, TIME_ZONE};
+
+pub type MensaSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// The deepest nesting of a query, e.g. meals → location → meals → allergens → language is 5
+const MAX_DEPTH: usize = 6;
+/// The highest complexity of a query. Every field counts 1, the meals of a location count 10 times their fields.
+const MAX_COMPLEXITY: usize = 500;
+
+/// Builds the GraphQL schema over the cached data. Queries are limited in depth and complexity,
+/// because the relations between meals and locations could otherwise be nested endlessly.
+pub fn schema(cache: Arc<Cache>, introspection: bool) -> MensaSchema {
+    let builder = Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(cache)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY);
+    match introspection {
+        true => builder.finish(),
+        false => builder.disable_introspection().finish(),
+    }
+}
+
+/// The filters of `/v2/meals`. Lists are comma separated like in the query string.
+#[derive(Debug, Clone, Default, InputObject, Serialize)]
+pub struct MealFilter {
+    date: Option<String>,
+    location: Option<String>,
+    exclude_allergens: Option<String>,
+    vegan: Option<bool>,
+    vegetarian: Option<bool>,
+    language: Option<String>,
+    min_price: Option<f32>,
+    max_price: Option<f32>,
+    price_category: Option<PriceCategory>,
+    q: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// The filters of `/v2/allergens`
+#[derive(Debug, Clone, Default, InputObject, Serialize)]
+pub struct AllergenFilter {
+    code: Option<String>,
+    name: Option<String>,
+    language: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// The filters of `/v2/locations`
+#[derive(Debug, Clone, Default, InputObject, Serialize)]
+pub struct LocationFilter {
+    code: Option<String>,
+    name: Option<String>,
+    city: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    /// The language of the names and cities of the locations
+    language: Option<String>,
+    sort: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Converts the filter into the query of the REST endpoint, so both share their filter logic
+fn to_query<Q: DeserializeOwned>(filter: &impl Serialize) -> async_graphql::Result<Q> {
+    Ok(serde_json::from_value(serde_json::to_value(filter)?)?)
+}
+
+fn find_meals(data: &Data, filter: &MealFilter) -> async_graphql::Result<Vec<Meal>> {
+    let mut query: MealsQuery = to_query(filter)?;
+    query.negotiate_languages(None, data);
+    Ok(query
+        .query_meals(data)?
+        .items
+        .into_iter()
+        .cloned()
+        .collect())
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn meals(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: MealFilter,
+    ) -> async_graphql::Result<Vec<Meal>> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        find_meals(cache.get_data()?.get_data(), &filter)
+    }
+
+    async fn allergens(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: AllergenFilter,
+    ) -> async_graphql::Result<Vec<Allergen>> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        let data = cache.get_data()?;
+        let mut query: AllergensQuery = to_query(&filter)?;
+        query.negotiate_languages(None, &data);
+        Ok(query
+            .query(data.get_allergens())?
+            .items
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    async fn locations(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: LocationFilter,
+    ) -> async_graphql::Result<Vec<APILocation>> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        let data = cache.get_data()?;
+        let mut query: LocationsQuery = to_query(&filter)?;
+        query.negotiate_languages(None, &data);
+        Ok(query
+            .query(data.get_locations())?
+            .items
+            .into_iter()
+            .map(|location| data.localize(location, query.label_language()))
+            .collect())
+    }
+
+    async fn languages(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Language>> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        Ok(cache
+            .get_data()?
+            .get_languages()
+            .iter()
+            .map(|language| language.language())
+            .collect())
+    }
+}
+
+#[Object]
+impl Meal {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn date(&self) -> &str {
+        &self.date
+    }
+
+    async fn price(&self) -> &Prices {
+        &self.price
+    }
+
+    async fn vegan(&self) -> bool {
+        self.vegan
+    }
+
+    async fn vegetarian(&self) -> bool {
+        self.vegetarian
+    }
+
+    /// The location with its full details in the language of the meal
+    async fn location(&self, ctx: &Context<'_>) -> async_graphql::Result<APILocation> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        let data = cache.get_data()?;
+        Ok(data
+            .get_locations()
+            .iter()
+            .find(|location| location.code == self.location.code)
+            .map(|location| data.localize(location, &self.language.code))
+            .unwrap_or_else(|| self.location.clone()))
+    }
+
+    async fn allergens(&self) -> &Vec<Allergen> {
+        &self.allergens
+    }
+
+    async fn language(&self) -> &Language {
+        &self.language
+    }
+}
+
+#[Object]
+impl APILocation {
+    async fn code(&self) -> &str {
+        &self.code
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn city(&self) -> &str {
+        &self.city
+    }
+
+    async fn available_languages(&self) -> &Vec<Language> {
+        &self.available_languages
+    }
+
+    async fn address(&self) -> Option<&str> {
+        self.address.as_deref()
+    }
+
+    async fn coordinates(&self) -> Option<GraphQLCoordinates> {
+        self.coordinates.map(|c| GraphQLCoordinates {
+            lat: c.lat,
+            lon: c.lon,
+        })
+    }
+
+    /// The opening hours as in `/v2/locations`
+    async fn opening_hours(&self) -> async_graphql::Json<&OpeningHours> {
+        async_graphql::Json(&self.opening_hours)
+    }
+
+    /// `null` if the opening hours of the location are unknown
+    async fn open_now(&self) -> Option<bool> {
+        self.opening_hours
+            .is_open(chrono::Utc::now().with_timezone(&TIME_ZONE))
+    }
+
+    async fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The meals of the location. The `location` of the filter is ignored.
+    #[graphql(complexity = "10 * child_complexity")]
+    async fn meals(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] filter: MealFilter,
+    ) -> async_graphql::Result<Vec<Meal>> {
+        let cache = ctx.data_unchecked::<Arc<Cache>>();
+        let filter = MealFilter {
+            location: Some(self.code.clone()),
+            ..filter
+        };
+        find_meals(cache.get_data()?.get_data(), &filter)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(name = "Coordinates")]
+struct GraphQLCoordinates {
+    lat: f64,
+    lon: f64,
+}
+
+#[Object]
+impl Allergen {
+    async fn code(&self) -> &str {
+        &self.code
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn language(&self) -> &Language {
+        &self.language
+    }
+}
+
+#[Object]
+impl Language {
+    /// The native name of the language
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The ISO 639 language code
+    async fn code(&self) -> &str {
+        &self.code
+    }
+}
+
+#[Object]
+impl Prices {
+    async fn students(&self) -> f32 {
+        self.get(PriceCategory::Students)
+    }
+
+    async fn employees(&self) -> f32 {
+        self.get(PriceCategory::Employees)
+    }
+
+    async fn guests(&self) -> f32 {
+        self.get(PriceCategory::Guests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn execute(query: &str, introspection: bool) -> async_graphql::Response {
+        let cache = Arc::new(Cache::new(chrono::Duration::minutes(1)).unwrap());
+        futures::executor::block_on(schema(cache, introspection).execute(query))
+    }
+
+    fn errors(query: &str) -> Vec<String> {
+        execute(query, true)
+            .errors
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_the_usual_queries() {
+        // Without cached data the query fails in the resolver, after it was validated
+        let errors = errors("{ locations { name meals { name allergens { name } } } }");
+        assert!(
+            errors.iter().all(|e| e.contains("Failed to get data")),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_deeply_nested_queries() {
+        let errors =
+            errors("{ meals { location { meals { location { meals { location { name } } } } } } }");
+        assert!(
+            errors.iter().any(|e| e.contains("nested too deep")),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_complex_queries() {
+        let fields = "name date vegan vegetarian price { students employees guests } allergens { code name }";
+        let query = format!(
+            "{{ locations {{ a: meals {{ {fields} }} b: meals {{ {fields} }} c: meals {{ {fields} }} d: meals {{ {fields} }} e: meals {{ {fields} }} }} }}"
+        );
+        let errors = errors(&query);
+        assert!(
+            errors.iter().any(|e| e.contains("too complex")),
+            "{errors:?}"
+        );
+    }
+
+    #[test]
+    fn disables_introspection() {
+        let query = "{ __schema { queryType { name } } }";
+        let schema_of = |introspection| {
+            execute(query, introspection).data.into_json().unwrap()["__schema"].clone()
+        };
+        assert!(!schema_of(true).is_null());
+        assert!(schema_of(false).is_null());
+    }
+}
@@ -0,0 +1,178 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::model::{APILocation, Meal, MealView, NestedLocation, PriceCategory};
+
+/// The nested shapes the meals of a response can be grouped into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum GroupBy {
+    #[serde(rename = "date")]
+    Date,
+    #[serde(rename = "location")]
+    Location,
+    #[serde(rename = "date,location")]
+    DateAndLocation,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum MealGroup<'a> {
+    Date {
+        date: &'a str,
+        meals: Vec<MealView<'a>>,
+    },
+    Location {
+        location: NestedLocation<'a>,
+        meals: Vec<MealView<'a>>,
+    },
+    DateAndLocation {
+        date: &'a str,
+        #[schema(no_recursion)]
+        locations: Vec<MealGroup<'a>>,
+    },
+}
+
+/// What the menu pages of the locations list, which tells closed days apart from days without matching meals
+pub trait DayListing {
+    /// Whether the menu page of the location lists the day
+    fn lists(&self, location: &APILocation, date: &str) -> bool;
+    /// Whether the location serves any meals on the day, matching the filters or not
+    fn serves(&self, location: &APILocation, date: &str) -> bool;
+}
+
+/// Groups the meals into the requested shape while keeping their order.
+/// An empty group means the location is closed: its menu page lists the day without any meals.
+/// Days that only lack meals matching the filters are left out, so clients do not mistake them for closed days.
+/// So are days the page did not list, e.g. because it failed to load, so clients can tell them apart as missing data.
+pub fn group_meals<'a>(
+    group_by: GroupBy,
+    meals: &[&'a Meal],
+    days: &[&'a String],
+    locations: &[&'a APILocation],
+    listing: &dyn DayListing,
+    price_category: Option<PriceCategory>,
+) -> Vec<MealGroup<'a>> {
+    let views = |accepts: &dyn Fn(&Meal) -> bool| -> Vec<MealView<'a>> {
+        meals
+            .iter()
+            .filter(|meal| accepts(meal))
+            .map(|meal| MealView::new(meal, price_category))
+            .collect()
+    };
+    // Whether at least one of the locations lists one of the days, and all of them are closed on the days they list
+    let all_closed = |locations: &[&APILocation], dates: &[&String]| {
+        let listed: Vec<_> = locations
+            .iter()
+            .cartesian_product(dates)
+            .filter(|(location, date)| listing.lists(location, date))
+            .collect();
+        !listed.is_empty()
+            && listed
+                .iter()
+                .all(|(location, date)| !listing.serves(location, date))
+    };
+
+    match group_by {
+        GroupBy::Date => days
+            .iter()
+            .filter_map(|date| {
+                let meals = views(&|meal| &meal.date == *date);
+                (!meals.is_empty() || all_closed(locations, &[date]))
+                    .then_some(MealGroup::Date { date, meals })
+            })
+            .collect(),
+        GroupBy::Location => locations
+            .iter()
+            .filter_map(|location| {
+                let meals = views(&|meal| meal.location.code == location.code);
+                (!meals.is_empty() || all_closed(&[location], days)).then(|| MealGroup::Location {
+                    location: NestedLocation::from(*location),
+                    meals,
+                })
+            })
+            .collect(),
+        GroupBy::DateAndLocation => days
+            .iter()
+            .filter_map(|date| {
+                let locations: Vec<_> = locations
+                    .iter()
+                    .filter_map(|location| {
+                        let meals = views(&|meal| {
+                            &meal.date == *date && meal.location.code == location.code
+                        });
+                        (!meals.is_empty() || all_closed(&[location], &[date])).then(|| {
+                            MealGroup::Location {
+                                location: NestedLocation::from(*location),
+                                meals,
+                            }
+                        })
+                    })
+                    .collect();
+                (!locations.is_empty()).then_some(MealGroup::DateAndLocation { date, locations })
+            })
+            .collect(),
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::api_filter::{APIFilter, MealsQuery};
+    use crate::fixtures;
+
+    /// One line for every location of every date, e.g. `2024-10-14 HL_ME: Gemüsecurry`
+    fn summary(groups: &[MealGroup]) -> Vec<String> {
+        let json = serde_json::to_value(groups).unwrap();
+        let mut lines = vec![];
+        for day in json.as_array().unwrap() {
+            for location in day["locations"].as_array().unwrap() {
+                let meals: Vec<_> = location["meals"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|meal| meal["name"].as_str().unwrap())
+                    .collect();
+                lines.push(format!(
+                    "{} {}: {}",
+                    day["date"].as_str().unwrap(),
+                    location["location"]["code"].as_str().unwrap(),
+                    meals.join(", ")
+                ));
+            }
+        }
+        lines
+    }
+
+    #[tokio::test]
+    async fn leaves_out_the_days_without_matching_meals_but_not_the_closed_ones() {
+        let data = fixtures::data("grouping").await;
+        let days = fixtures::days(0);
+        let mut query = MealsQuery::default().with_vegan(true);
+        query.negotiate_languages(None, &data);
+        let meals = query.filter_meals(&data);
+        let all_days: Vec<_> = data.get_days().iter().collect();
+        let locations: Vec<_> = data.get_locations().iter().collect();
+
+        let groups = group_meals(
+            GroupBy::DateAndLocation,
+            &meals,
+            &all_days,
+            &locations,
+            &query.listing(&data),
+            None,
+        );
+
+        assert_eq!(
+            summary(&groups),
+            [
+                format!("{} HL_MH: Linsen-Dal", days[0]),
+                format!("{} HL_ME: Gemüsecurry", days[0]),
+                // The cafeteria is closed, the other locations serve no vegan meals
+                format!("{} HL_CA: ", days[1]),
+                format!("{} HL_CA: Falafel", days[2]),
+                format!("{} HL_ME: ", days[2]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{Days, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::model::Meal;
+use crate::opening_hours::TIME_ZONE;
+
+/// Remembers which meals have been served on which day at which location.
+/// Unlike the cache it keeps meals after they vanished from the upstream website.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    entries: BTreeSet<HistoryEntry>,
+    /// How many days entries are kept. Forever, if not set
+    #[serde(skip)]
+    retention_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub(crate) date: String,
+    pub(crate) location: String,
+    pub(crate) language: String,
+    pub(crate) name: String,
+}
+
+impl History {
+    /// Loads the history from a JSON file. Starts with an empty history if the file does not exist yet.
+    /// A corrupt file is moved aside with a warning, so it does not keep the API from starting.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(history) => Ok(history),
+                Err(e) => {
+                    let corrupt = with_suffix(path, ".corrupt");
+                    eprintln!(
+                        "Failed to parse the history {}, moved it to {} and starting with an empty history: {e}",
+                        path.display(),
+                        corrupt.display()
+                    );
+                    std::fs::rename(path, corrupt)?;
+                    Ok(Self::default())
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Keeps the entries of the given number of days only
+    pub fn with_retention(mut self, days: u32) -> Self {
+        self.retention_days = Some(days);
+        self
+    }
+
+    /// Writes the history to a temporary file and renames it, so a crash while writing does not leave a truncated file.
+    /// Blocks, so call it outside of the async runtime.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let temporary = with_suffix(path, ".tmp");
+        std::fs::write(&temporary, serde_json::to_string(self)?)?;
+        std::fs::rename(temporary, path)?;
+        Ok(())
+    }
+
+    /// Records the meals served until today and forgets the entries older than the retention.
+    /// Meals of later days are recorded once they are served, because the website may still replace them.
+    pub fn record(&mut self, meals: &[Meal]) {
+        let today = Utc::now().with_timezone(&TIME_ZONE).date_naive();
+        let today_str = today.format("%Y-%m-%d").to_string();
+        self.entries.extend(
+            meals
+                .iter()
+                .filter(|meal| meal.date <= today_str)
+                .map(|meal| HistoryEntry {
+                    date: meal.date.clone(),
+                    location: meal.location.code.clone(),
+                    language: meal.language.code.clone(),
+                    name: meal.name.clone(),
+                }),
+        );
+
+        if let Some(days) = self.retention_days {
+            let oldest = (today - Days::new(days as u64))
+                .format("%Y-%m-%d")
+                .to_string();
+            self.entries.retain(|entry| entry.date >= oldest);
+        }
+    }
+
+    /// Counts on how many distinct days and locations an accepted meal has been served
+    pub fn count_served(&self, accepts: impl Fn(&HistoryEntry) -> bool) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| accepts(entry))
+            .map(|entry| (&entry.date, &entry.location))
+            .collect::<BTreeSet<_>>()
+            .len()
+    }
+}
+
+/// The path with the suffix appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    name.into()
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::iter::once;
+
+use anyhow::anyhow;
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::model::{APILocation, Language};
+
+/// The languages that are shipped with the API
+const DEFAULT_LANGUAGES: &str = include_str!("../languages.toml");
+
+/// The original language of the studentenwerk.sh website. Used if no language is requested.
+pub const DEFAULT_LANGUAGE: &str = "de";
+
+/// All languages the API scrapes, as defined in the languages file
+#[derive(Debug, Clone)]
+pub struct Languages {
+    languages: Vec<LanguageConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LanguagesFile {
+    language: Vec<LanguageConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageConfig {
+    /// The ISO 639 language code
+    pub(crate) code: String,
+    /// The native name of the language
+    pub(crate) name: String,
+    /// The path of the menu page on the studentenwerk.sh website
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) menu_slug: String,
+    /// The path of the page the allergens are scraped from
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) allergen_slug: String,
+    /// The languages that are served instead, if a location has no menu in this language
+    #[serde(default)]
+    pub(crate) fallback: Vec<String>,
+    /// The translations of the german labels defined by the API, e.g. location names
+    #[serde(default)]
+    pub(crate) translations: Translations,
+}
+
+/// The translations of the labels the API defines itself, keyed by stable codes, so changed german labels keep their translation
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Translations {
+    /// The names and cities of the locations by location code
+    #[serde(default)]
+    locations: HashMap<String, LocationTranslation>,
+    /// The names of the allergens the API defines itself by allergen code
+    #[serde(default)]
+    allergens: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct LocationTranslation {
+    name: Option<String>,
+    city: Option<String>,
+}
+
+impl Languages {
+    /// Loads the languages from the given TOML file or falls back to the languages shipped with the API
+    pub fn load(path: Option<&str>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::from_toml(&std::fs::read_to_string(path)?),
+            None => Self::from_toml(DEFAULT_LANGUAGES),
+        }
+    }
+
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        let file: LanguagesFile = toml::from_str(toml)?;
+        let languages = Self {
+            languages: file.language,
+        };
+        languages.validate()?;
+        Ok(languages)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(code) = self.languages.iter().map(|l| &l.code).duplicates().next() {
+            return Err(anyhow!("Language {code} is defined more than once"));
+        }
+        self.get(DEFAULT_LANGUAGE).ok_or(anyhow!(
+            "The default language {DEFAULT_LANGUAGE} is not defined"
+        ))?;
+
+        for language in &self.languages {
+            for fallback in &language.fallback {
+                self.get(fallback).ok_or(anyhow!(
+                    "Fallback {fallback} of language {} is not defined",
+                    language.code
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LanguageConfig> + Clone {
+        self.languages.iter()
+    }
+
+    pub fn get(&self, code: &str) -> Option<&LanguageConfig> {
+        self.languages.iter().find(|l| l.code == code)
+    }
+}
+
+impl LanguageConfig {
+    pub fn language(&self) -> Language {
+        Language {
+            name: self.name.clone(),
+            code: self.code.clone(),
+        }
+    }
+
+    /// The name of the location in the language. `None` if there is no translation.
+    pub fn location_name(&self, location_code: &str) -> Option<&str> {
+        self.translations
+            .locations
+            .get(location_code)
+            .and_then(|location| location.name.as_deref())
+    }
+
+    /// The city of the location in the language. `None` if there is no translation.
+    pub fn location_city(&self, location_code: &str) -> Option<&str> {
+        self.translations
+            .locations
+            .get(location_code)
+            .and_then(|location| location.city.as_deref())
+    }
+
+    /// The name of an allergen the API defines itself in the language. `None` if there is no translation.
+    pub fn allergen_name(&self, allergen_code: &str) -> Option<&str> {
+        self.translations
+            .allergens
+            .get(allergen_code)
+            .map(|name| name.as_str())
+    }
+}
+
+/// The languages served for a request after negotiating the requested languages
+/// with the languages the data is available in
+#[derive(Debug, Clone, Default)]
+pub struct ServedLanguages {
+    /// The codes of the languages served for each location
+    by_location: HashMap<String, Vec<String>>,
+    /// The codes of the languages served for data that does not belong to a location
+    general: Vec<String>,
+}
+
+impl ServedLanguages {
+    /// Negotiates the served languages.
+    /// Explicitly requested languages take precedence over the Accept-Language header.
+    /// Locations that are not available in any requested language fall back along the fallback chain.
+    pub fn negotiate<'a>(
+        requested: Option<&[String]>,
+        accept_language: Option<&str>,
+        languages: &Languages,
+        locations: impl Iterator<Item = &'a APILocation>,
+    ) -> Self {
+        let explicit = requested.is_some();
+        let mut preferred: Vec<String> = match requested {
+            Some(requested) => requested.to_vec(),
+            None => parse_accept_language(accept_language.unwrap_or_default())
+                .into_iter()
+                .filter(|code| languages.get(code).is_some())
+                .collect(),
+        };
+        if preferred.is_empty() {
+            preferred.push(DEFAULT_LANGUAGE.to_string());
+        }
+
+        let chain: Vec<&str> = preferred
+            .iter()
+            .flat_map(|code| {
+                once(code.as_str()).chain(
+                    languages
+                        .get(code)
+                        .into_iter()
+                        .flat_map(|l| l.fallback.iter().map(|f| f.as_str())),
+                )
+            })
+            .chain(once(DEFAULT_LANGUAGE))
+            .unique()
+            .collect();
+
+        let resolve = |available: &[&str]| -> Vec<String> {
+            let served: Vec<String> = if explicit {
+                preferred
+                    .iter()
+                    .filter(|code| available.contains(&code.as_str()))
+                    .cloned()
+                    .collect()
+            } else {
+                vec![]
+            };
+            if !served.is_empty() {
+                return served;
+            }
+            chain
+                .iter()
+                .find(|code| available.contains(code))
+                .map(|code| vec![code.to_string()])
+                .unwrap_or_else(|| preferred.clone())
+        };
+
+        let all_languages: Vec<&str> = languages.iter().map(|l| l.code.as_str()).collect();
+        Self {
+            by_location: locations
+                .map(|location| {
+                    let available: Vec<&str> = location
+                        .available_languages
+                        .iter()
+                        .map(|l| l.code.as_str())
+                        .collect();
+                    (location.code.clone(), resolve(&available))
+                })
+                .collect(),
+            general: resolve(&all_languages),
+        }
+    }
+
+    /// The language labels are served in, the first of the languages served for data that does not belong to a location
+    pub fn primary(&self) -> &str {
+        self.general
+            .first()
+            .map(|code| code.as_str())
+            .unwrap_or(DEFAULT_LANGUAGE)
+    }
+
+    /// Whether the language is served for data that does not belong to a location
+    pub fn accepts(&self, language_code: &str) -> bool {
+        self.general.iter().any(|code| code == language_code)
+    }
+
+    /// Whether the language is served for the location
+    pub fn accepts_at(&self, location_code: &str, language_code: &str) -> bool {
+        self.by_location
+            .get(location_code)
+            .is_some_and(|codes| codes.iter().any(|code| code == language_code))
+    }
+
+    /// The codes of the languages served for any of the locations
+    pub fn codes_at_locations(&self) -> Vec<String> {
+        self.by_location
+            .values()
+            .flatten()
+            .unique()
+            .cloned()
+            .collect()
+    }
+
+    /// The value of the Content-Language header
+    pub fn content_language(&self, by_location: bool) -> String {
+        if by_location {
+            self.by_location
+                .values()
+                .flatten()
+                .unique()
+                .sorted()
+                .join(", ")
+        } else {
+            self.general.join(", ")
+        }
+    }
+}
+
+/// Extracts the language codes from an Accept-Language header, ordered by their quality value.
+/// Regions are ignored, so "en-US" is read as "en".
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    header
+        .split(",")
+        .filter_map(|entry| {
+            let mut parts = entry.split(";").map(|part| part.trim());
+            let tag = parts.next()?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map(|q| q.parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            let code = tag.split("-").next()?.to_lowercase();
+            (quality > 0.0 && !code.is_empty() && code != "*").then_some((code, quality))
+        })
+        .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+        .map(|(code, _)| code)
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_the_bundled_labels_by_code() {
+        let languages = Languages::load(None).unwrap();
+        let english = languages.get("en").unwrap();
+        assert_eq!(english.location_name("HL_ME"), Some("Canteen"));
+        assert_eq!(english.location_city("HL_ME"), Some("Lubeck"));
+        assert_eq!(english.location_name("KI_CA1"), None);
+        assert_eq!(english.allergen_name("alko"), Some("Alcohol"));
+        assert_eq!(languages.get("de").unwrap().location_name("HL_ME"), None);
+    }
+}
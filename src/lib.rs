@@ -1,5 +1,29 @@
 pub mod api_filter;
+#[cfg(feature = "server")]
+pub mod archive;
+#[cfg(feature = "server")]
 pub mod cache;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "server")]
 pub mod config;
+pub mod diagnostics;
+#[cfg(feature = "fixtures")]
+#[doc(hidden)]
+pub mod fixtures;
+#[cfg(feature = "server")]
+pub mod graphql;
+pub mod grouping;
+pub mod history;
+pub mod languages;
+#[cfg(feature = "server")]
+pub mod locations;
 pub mod model;
-mod scrapers;
+pub mod opening_hours;
+#[cfg(feature = "server")]
+pub mod refresh;
+#[cfg(feature = "server")]
+pub mod schedule;
+#[cfg(feature = "server")]
+pub mod scrapers;
+pub mod search;
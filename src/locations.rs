@@ -0,0 +1,213 @@
+use anyhow::anyhow;
+use itertools::Itertools;
+use scraper::Selector;
+use serde::Deserialize;
+
+use crate::model::{APILocation, Coordinates};
+use crate::opening_hours::OpeningHours;
+
+/// The locations that are shipped with the API
+const DEFAULT_LOCATIONS: &str = include_str!("../locations.toml");
+
+/// All locations the API scrapes, as defined in the locations file
+#[derive(Debug, Clone)]
+pub struct Locations {
+    locations: Vec<LocationConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocationsFile {
+    location: Vec<LocationConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LocationConfig {
+    pub(crate) code: String,
+    pub(crate) name: String,
+    pub(crate) city: String,
+    /// The speiseplan website uses number codes to differentiate between locations.
+    /// This is the `ort` parameter of the website.
+    pub(crate) ort: usize,
+    /// The `mensa` parameter of the speiseplan website
+    pub(crate) mensa: usize,
+    #[serde(default)]
+    pub(crate) siblings: Siblings,
+    pub(crate) address: Option<String>,
+    pub(crate) coordinates: Option<Coordinates>,
+    #[serde(default)]
+    pub(crate) opening_hours: OpeningHours,
+    /// Where to scrape the regular opening hours from. Overrides the configured regular opening hours.
+    pub(crate) opening_hours_source: Option<OpeningHoursSource>,
+}
+
+/// A part of a page on the studentenwerk.sh website that lists opening hours
+#[derive(Debug, Clone, Deserialize)]
+pub struct OpeningHoursSource {
+    pub(crate) url: String,
+    /// The CSS selector of the elements containing the opening hours
+    pub(crate) selector: String,
+}
+
+/// The locations that share a page on the speiseplan website with this location
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Siblings {
+    mensa: Option<String>,
+    cafeteria: Option<String>,
+}
+
+impl Locations {
+    /// Loads the locations from the given TOML file or falls back to the locations shipped with the API
+    pub fn load(path: Option<&str>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::from_toml(&std::fs::read_to_string(path)?),
+            None => Self::from_toml(DEFAULT_LOCATIONS),
+        }
+    }
+
+    pub fn from_toml(toml: &str) -> anyhow::Result<Self> {
+        let file: LocationsFile = toml::from_str(toml)?;
+        let locations = Self {
+            locations: file.location,
+        };
+        locations.validate()?;
+        Ok(locations)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(code) = self.locations.iter().map(|l| &l.code).duplicates().next() {
+            return Err(anyhow!("Location {code} is defined more than once"));
+        }
+
+        for location in &self.locations {
+            if let Some(source) = &location.opening_hours_source {
+                Selector::parse(&source.selector).map_err(|e| {
+                    anyhow!(
+                        "Invalid opening hours selector of location {}: {e}",
+                        location.code
+                    )
+                })?;
+            }
+            for sibling in [location.mensa_sibling(), location.cafeteria_sibling()] {
+                self.get(sibling).ok_or(anyhow!(
+                    "Sibling {sibling} of location {} is not defined",
+                    location.code
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LocationConfig> {
+        self.locations.iter()
+    }
+
+    pub fn get(&self, code: &str) -> Option<&LocationConfig> {
+        self.locations.iter().find(|l| l.code == code)
+    }
+
+    /// Keeps the given locations and the locations sharing a page with them, so only their pages are scraped
+    pub fn only(&self, codes: &[String]) -> anyhow::Result<Self> {
+        let kept: Vec<&LocationConfig> = codes
+            .iter()
+            .map(|code| {
+                self.get(code)
+                    .ok_or(anyhow!("Location {code} is not defined"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let locations = Self {
+            locations: self
+                .locations
+                .iter()
+                .filter(|l| kept.iter().any(|k| k.ort == l.ort && k.mensa == l.mensa))
+                .cloned()
+                .collect(),
+        };
+        locations.validate()?;
+        Ok(locations)
+    }
+
+    /// One location of every page of the speiseplan website
+    pub(crate) fn pages(&self) -> impl Iterator<Item = &LocationConfig> + Clone {
+        self.locations.iter().unique_by(|l| (l.ort, l.mensa))
+    }
+
+    /// Whether the location shares its page on the speiseplan website with another location
+    pub(crate) fn is_double(&self, location: &LocationConfig) -> bool {
+        self.locations
+            .iter()
+            .any(|l| l.ort == location.ort && l.mensa == location.mensa && l.code != location.code)
+    }
+
+    /// The locations listed on the page of the location on the speiseplan website, including the location itself
+    pub(crate) fn sharing_page<'a>(
+        &'a self,
+        location: &'a LocationConfig,
+    ) -> impl Iterator<Item = &'a LocationConfig> {
+        self.locations
+            .iter()
+            .filter(|l| l.ort == location.ort && l.mensa == location.mensa)
+    }
+}
+
+impl LocationConfig {
+    pub(crate) fn mensa_sibling(&self) -> &str {
+        self.siblings.mensa.as_deref().unwrap_or(&self.code)
+    }
+
+    pub(crate) fn cafeteria_sibling(&self) -> &str {
+        self.siblings.cafeteria.as_deref().unwrap_or(&self.code)
+    }
+
+    /// The menu of the location on the studentenwerk.sh website
+    pub(crate) fn url(&self) -> String {
+        format!(
+            "https://studentenwerk.sh/de/essen-uebersicht?ort={}&mensa={}",
+            self.ort, self.mensa
+        )
+    }
+}
+
+impl From<&LocationConfig> for APILocation {
+    fn from(value: &LocationConfig) -> Self {
+        APILocation {
+            code: value.code.clone(),
+            name: value.name.clone(),
+            city: value.city.clone(),
+            available_languages: vec![],
+            address: value.address.clone(),
+            coordinates: value.coordinates,
+            opening_hours: value.opening_hours.clone(),
+            url: value.url(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opening_hours::TIME_ZONE;
+    use chrono::TimeZone;
+
+    #[test]
+    fn knows_the_opening_hours_of_the_bundled_locations() {
+        let locations = Locations::load(None).unwrap();
+        let wednesday_noon = TIME_ZONE.with_ymd_and_hms(2024, 6, 5, 12, 0, 0).unwrap();
+        let sunday_noon = TIME_ZONE.with_ymd_and_hms(2024, 6, 9, 12, 0, 0).unwrap();
+
+        for location in locations.iter() {
+            assert_eq!(
+                location.opening_hours.is_open(wednesday_noon),
+                Some(true),
+                "{}",
+                location.code
+            );
+            assert_eq!(
+                location.opening_hours.is_open(sunday_noon),
+                Some(false),
+                "{}",
+                location.code
+            );
+        }
+    }
+}
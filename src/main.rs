@@ -1,19 +1,39 @@
-use std::{convert::Infallible, sync::Arc};
+use std::{convert::Infallible, path::PathBuf, sync::Arc};
 
-use chrono::Utc;
-use htmlentity::data;
 use serde::Serialize;
-use tokio::sync::RwLock;
 
+use anyhow::anyhow;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_warp::GraphQLResponse;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use envconfig::Envconfig;
-use mensa_api::api_filter::{APIFilter, AllergensQuery, LocationsQuery, MealsQuery};
+use itertools::Itertools;
+use mensa_api::api_filter::{
+    APIFilter, AllergensQuery, DiagnosticsQuery, LocationsQuery, MealsQuery, NextMealsQuery, Page,
+    QueryError, Sortable, WithMeals,
+};
+use mensa_api::archive::{Archive, PageSource, Snapshot};
 use mensa_api::cache::Cache;
-use mensa_api::config::Config;
-use mensa_api::model::{APILocation, Allergen, Data, Meal};
+use mensa_api::config::{Config, Weeks};
+use mensa_api::diagnostics::{ScrapeWarning, WarningKind};
+use mensa_api::graphql::MensaSchema;
+use mensa_api::grouping::{group_meals, GroupBy, MealGroup};
+use mensa_api::history::History;
+use mensa_api::languages::Languages;
+use mensa_api::locations::Locations;
+use mensa_api::model::{Allergen, Data, LocationView, Meal, MealView, PriceCategory};
+use mensa_api::opening_hours::TIME_ZONE;
+use mensa_api::refresh::{RefreshQuery, RefreshReport, Refresher};
+use mensa_api::schedule::Schedule;
+use mensa_api::scrapers::Scraper;
+use sha2::{Digest, Sha256};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi, ToSchema};
+use warp::http::header::{HeaderValue, CONTENT_LANGUAGE};
 use warp::http::StatusCode;
 use warp::{
     reject::{Reject, Rejection},
-    reply::{self, Json, Reply},
+    reply::{self, Reply, Response},
     Filter,
 };
 
@@ -21,6 +41,18 @@ use warp::{
 struct APIError(anyhow::Error);
 impl Reject for APIError {}
 
+#[derive(Debug)]
+struct BadRequest(QueryError);
+impl Reject for BadRequest {}
+
+#[derive(Debug)]
+struct Unauthorized;
+impl Reject for Unauthorized {}
+
+#[derive(Debug)]
+struct RefreshFailed(anyhow::Error);
+impl Reject for RefreshFailed {}
+
 impl APIError {
     async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
         let code;
@@ -29,17 +61,29 @@ impl APIError {
         if err.is_not_found() {
             code = StatusCode::NOT_FOUND;
             message = "Not Found".into();
-        } else if let Some(_) = err.find::<warp::filters::body::BodyDeserializeError>() {
+        } else if err
+            .find::<warp::filters::body::BodyDeserializeError>()
+            .is_some()
+        {
             code = StatusCode::BAD_REQUEST;
             message = "Invalid Body".into();
         } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
             code = StatusCode::BAD_REQUEST;
             message = e.to_string();
-        } else if let Some(e) = err.find::<anyhow::Error>() {
+        } else if let Some(BadRequest(e)) = err.find::<BadRequest>() {
+            code = StatusCode::BAD_REQUEST;
+            message = e.to_string();
+        } else if err.find::<Unauthorized>().is_some() {
+            code = StatusCode::UNAUTHORIZED;
+            message = "Unauthorized".into();
+        } else if let Some(RefreshFailed(e)) = err.find::<RefreshFailed>() {
+            code = StatusCode::BAD_GATEWAY;
+            message = format!("Refresh failed: {e}");
+        } else if let Some(APIError(e)) = err.find::<APIError>() {
             eprint!("{e}");
             code = StatusCode::INTERNAL_SERVER_ERROR;
             message = "Internal Server Error".into()
-        } else if let Some(_) = err.find::<warp::reject::MethodNotAllowed>() {
+        } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
             code = StatusCode::METHOD_NOT_ALLOWED;
             message = "Method Not Allowed".into();
         } else {
@@ -48,9 +92,7 @@ impl APIError {
             message = "Internal Server Error".into();
         }
 
-        let json = warp::reply::json(&ErrorResponse {
-            message: message.into(),
-        });
+        let json = warp::reply::json(&ErrorResponse { message });
 
         Ok(warp::reply::with_status(json, code))
     }
@@ -60,94 +102,460 @@ fn custom_reject(error: impl Into<anyhow::Error>) -> warp::Rejection {
     warp::reject::custom(APIError(error.into()))
 }
 
-#[derive(Debug, Serialize)]
-struct DefaultResponse<'a, T> {
+fn bad_request(error: QueryError) -> warp::Rejection {
+    warp::reject::custom(BadRequest(error))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DefaultResponse<T> {
     last_updated: String,
-    data: Vec<&'a T>,
+    /// The number of elements matching the query before pagination
+    total: usize,
+    data: Vec<T>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+struct NextMealsResponse<T> {
+    last_updated: String,
+    total: usize,
+    data: Vec<T>,
+    /// How often matching meals have been served before today
+    past_occurrences: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 struct ErrorResponse {
     message: String,
 }
 
-type State = Arc<RwLock<Cache>>;
+type State = Arc<Cache>;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Mensa-API",
+        description = "Menus of the canteens of the Studentenwerk Schleswig-Holstein"
+    ),
+    servers((url = "https://speiseplan.mcloud.digital")),
+    paths(
+        meals_handler,
+        next_meals_handler,
+        allergens_handler,
+        locations_handler,
+        nearby_locations_handler,
+        diagnostics_handler,
+        refresh_handler
+    ),
+    components(schemas(MealGroup, PriceCategory, GroupBy, WithMeals, WarningKind)),
+    modifiers(&AdminTokenSecurity)
+)]
+struct ApiDoc;
+
+struct AdminTokenSecurity;
+
+impl Modify for AdminTokenSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+            );
+    }
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8">
+    <title>Mensa-API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui.css">
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5.17.14/swagger-ui-bundle.js"></script>
+    <script>
+      SwaggerUIBundle({ url: "/v2/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>"##;
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Scrapes the menus of the Studentenwerk Schleswig-Holstein"
+)]
+struct Cli {
+    /// Starts the web server, if omitted
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Starts the web server
+    Serve,
+    /// Scrapes the menus once and prints the meals
+    Scrape(ScrapeArgs),
+    /// Parses an archived snapshot again and prints the meals.
+    /// Records the meals in the history of `HISTORY_FILE`, if set.
+    Replay(ReplayArgs),
+}
+
+#[derive(Debug, Args)]
+struct ScrapeArgs {
+    /// Only scrapes this week, 0 being the current week. Defaults to the weeks of `WEEKS`
+    #[arg(long)]
+    week: Option<usize>,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+struct ReplayArgs {
+    /// The snapshot directory in the archive
+    snapshot: PathBuf,
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Debug, Args)]
+struct OutputArgs {
+    /// Only scrapes and prints the meals of these locations
+    #[arg(long, value_delimiter = ',')]
+    location: Vec<String>,
+    /// Only prints the meals in these languages. Defaults to all languages
+    #[arg(long, value_delimiter = ',')]
+    language: Vec<String>,
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Csv,
+}
+
+/// A meal as a row of the CSV output
+#[derive(Debug, Serialize)]
+struct MealRecord<'a> {
+    date: &'a str,
+    location: &'a str,
+    language: &'a str,
+    name: &'a str,
+    vegan: bool,
+    vegetarian: bool,
+    price_students: f32,
+    price_employees: f32,
+    price_guests: f32,
+    /// Comma separated allergen codes
+    allergens: String,
+}
+
+impl<'a> From<&'a Meal> for MealRecord<'a> {
+    fn from(meal: &'a Meal) -> Self {
+        Self {
+            date: meal.get_date(),
+            location: meal.get_location().get_code(),
+            language: meal.get_language().get_code(),
+            name: meal.get_name(),
+            vegan: meal.is_vegan(),
+            vegetarian: meal.is_vegetarian(),
+            price_students: meal.get_price().get(PriceCategory::Students),
+            price_employees: meal.get_price().get(PriceCategory::Employees),
+            price_guests: meal.get_price().get(PriceCategory::Guests),
+            allergens: meal.get_allergens().iter().map(|a| a.get_code()).join(","),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
     if let Err(e) = run().await {
-        eprint!("{e}")
+        eprintln!("{e}");
+        std::process::exit(1);
     }
 }
 
 async fn run() -> anyhow::Result<()> {
+    let cli = Cli::parse();
     let config = Config::init_from_env()?;
-    let state = Arc::new(RwLock::new(Cache::new(chrono::Duration::seconds(
-        config.ttl as i64,
-    ))?));
-
-    // Fetch the data every ttl seconds in extra thread
-    tokio::spawn({
-        let state = state.clone();
-        async move {
-            loop {
-                println!("{} Starting to load data...", chrono::Local::now());
-                let data = Cache::fetch_data().await;
-                match data {
-                    Ok(data) => {
-                        let mut writable = state.write().await;
-                        writable.set_data(data);
-                        println!("{} Cache refreshed...", chrono::Local::now());
-                    }
-                    Err(e) => eprint!("{e}"),
-                }
-                tokio::time::sleep(std::time::Duration::from_secs(config.ttl as u64)).await;
+    let mut scraper = Scraper::new(
+        Locations::load(config.locations_file.as_deref())?,
+        Languages::load(config.languages_file.as_deref())?,
+    )
+    .with_weeks(config.weeks);
+    if let Some(archive_dir) = &config.archive_dir {
+        scraper = scraper.with_source(PageSource::Archived(
+            Archive::new(
+                archive_dir,
+                chrono::Duration::days(config.archive_retention_days as i64),
+            )
+            .with_max_size(config.archive_max_size_mb * 1024 * 1024),
+        ));
+    }
+
+    match cli.command {
+        Some(Command::Scrape(args)) => scrape(scraper, args).await,
+        Some(Command::Replay(args)) => replay(config, scraper, args).await,
+        Some(Command::Serve) | None => serve(config, scraper).await,
+    }
+}
+
+/// Restricts the scraper to the pages of the requested locations
+fn only_locations(scraper: Scraper, output: &OutputArgs) -> anyhow::Result<Scraper> {
+    match output.location.is_empty() {
+        true => Ok(scraper),
+        false => {
+            let locations = scraper.get_locations().only(&output.location)?;
+            Ok(scraper.with_locations(locations))
+        }
+    }
+}
+
+async fn scrape(scraper: Scraper, args: ScrapeArgs) -> anyhow::Result<()> {
+    let scraper = match args.week {
+        Some(week) => scraper.with_weeks(Weeks::Single(week)),
+        None => scraper,
+    };
+    let scraper = only_locations(scraper, &args.output)?;
+    let data = scraper.fetch().await?;
+    print_meals(&data, &args.output)
+}
+
+async fn replay(config: Config, scraper: Scraper, args: ReplayArgs) -> anyhow::Result<()> {
+    let snapshot = Snapshot::load(&args.snapshot)?;
+    let data = only_locations(scraper, &args.output)?
+        .replay(&snapshot)
+        .await?;
+
+    if let Some(history_file) = &config.history_file {
+        let mut history =
+            History::load(history_file)?.with_retention(config.history_retention_days);
+        history.record(data.get_meals());
+        history.save(history_file)?;
+    }
+    print_meals(&data, &args.output)
+}
+
+fn print_meals(data: &Data, output: &OutputArgs) -> anyhow::Result<()> {
+    let query = match output.location.is_empty() {
+        true => MealsQuery::default(),
+        false => MealsQuery::default().with_locations(&output.location),
+    };
+    // Without a language the query serves only the default language
+    let query = match output.language.is_empty() {
+        true => query.with_languages(
+            data.get_languages()
+                .iter()
+                .map(|l| l.language().get_code().to_string()),
+        ),
+        false => query.with_languages(&output.language),
+    };
+    let meals = query.filter_meals(data);
+
+    let stdout = std::io::stdout().lock();
+    match output.format {
+        Format::Json => serde_json::to_writer_pretty(stdout, &meals)?,
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(stdout);
+            for meal in meals {
+                writer.serialize(MealRecord::from(meal))?;
             }
+            writer.flush()?;
         }
-    });
+    }
+    Ok(())
+}
+
+async fn serve(config: Config, scraper: Scraper) -> anyhow::Result<()> {
+    let cache = Cache::new(chrono::Duration::seconds(config.ttl as i64))?;
+    let history = match &config.history_file {
+        Some(history_file) => History::load(history_file)?,
+        None => History::default(),
+    };
+    cache.set_history(history.with_retention(config.history_retention_days));
+    let schedule = Schedule::load(config.schedule_file.as_deref(), config.ttl)?;
+    let state = Arc::new(cache);
+    let refresher = Arc::new(Refresher::new(
+        scraper,
+        state.clone(),
+        config.history_file.clone(),
+    ));
+
+    // Fetch the data on schedule in extra thread
+    tokio::spawn(refresher.clone().run_schedule(schedule));
+
+    println!("Server starting on port {}", config.port);
+    warp::serve(routes(&config, state, refresher))
+        .run(([0, 0, 0, 0], config.port))
+        .await;
 
-    // Webserver routes
+    Ok(())
+}
+
+/// The routes of the webserver
+fn routes(
+    config: &Config,
+    state: State,
+    refresher: Arc<Refresher>,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone {
     let info_route = warp::path!("v2")
         .or(warp::path::end())
         .map(|_| "Mensa-API v2\nMade with 💙 in Lübeck\nhttps://github.com/Draculente/mensa-api/");
 
     let meals_route = warp::path!("v2" / "meals")
-        .and(with_state_and_query_filter::<Meal, MealsQuery>(
-            state.clone(),
-        ))
-        .and_then(move |query, state| default_handler(query, state, |d| d.get_meals()));
+        .and(warp::query::<MealsQuery>())
+        .and(accept_language())
+        .and(with_state(state.clone()))
+        .and_then(meals_handler);
+
+    let next_meals_route = warp::path!("v2" / "meals" / "next")
+        .and(warp::query::<NextMealsQuery>())
+        .and(accept_language())
+        .and(with_state(state.clone()))
+        .and_then(next_meals_handler);
 
     let allergens_route = warp::path!("v2" / "allergenes")
         .or(warp::path!("v2" / "allergens"))
-        .and(with_state_and_query_filter::<Allergen, AllergensQuery>(
-            state.clone(),
-        ))
-        .and_then(move |_, query, state| default_handler(query, state, |d| d.get_allergens()));
+        .unify()
+        .and(warp::query::<AllergensQuery>())
+        .and(accept_language())
+        .and(with_state(state.clone()))
+        .and_then(allergens_handler);
 
     let locations_route = warp::path!("v2" / "locations")
-        .and(with_state_and_query_filter::<APILocation, LocationsQuery>(
-            state.clone(),
-        ))
-        .and_then(move |query, state| default_handler(query, state, |d| d.get_locations()));
-
-    let routes = meals_route
-        .or(allergens_route)
-        .or(locations_route)
-        .or(info_route)
-        .with(warp::cors().allow_any_origin())
-        .and(warp::get())
-        .recover(APIError::handle_rejection);
+        .and(warp::query::<LocationsQuery>())
+        .and(accept_language())
+        .and(with_state(state.clone()))
+        .and_then(locations_handler);
 
-    println!("Server starting on port {}", config.port);
-    warp::serve(routes).run(([0, 0, 0, 0], config.port)).await;
+    let nearby_locations_route = warp::path!("v2" / "locations" / "nearby")
+        .and(warp::query::<LocationsQuery>())
+        .and(accept_language())
+        .and(with_state(state.clone()))
+        .and_then(nearby_locations_handler);
 
-    Ok(())
+    let diagnostics_route = warp::path!("v2" / "diagnostics")
+        .and(with_admin_token(config.admin_token.clone()))
+        .and(warp::query::<DiagnosticsQuery>())
+        .and(with_state(state.clone()))
+        .and_then(diagnostics_handler);
+
+    let refresh_route = warp::path!("admin" / "refresh")
+        .and(warp::post())
+        .and(with_admin_token(config.admin_token.clone()))
+        .and(warp::query::<RefreshQuery>())
+        .and(with_refresher(refresher))
+        .and_then(refresh_handler);
+
+    let openapi_route = warp::path!("v2" / "openapi.json").map(|| reply::json(&ApiDoc::openapi()));
+
+    let docs_route = warp::path!("v2" / "docs").map(|| reply::html(SWAGGER_UI));
+
+    let schema = mensa_api::graphql::schema(state.clone(), config.graphiql);
+    let graphql_route = warp::path!("v2" / "graphql")
+        .and(warp::post())
+        .and(async_graphql_warp::graphql(schema))
+        .and_then(
+            |(schema, request): (MensaSchema, async_graphql::Request)| async move {
+                Ok::<_, Infallible>(GraphQLResponse::from(schema.execute(request).await))
+            },
+        );
+
+    let graphiql = config.graphiql;
+    let graphiql_route = warp::path!("v2" / "graphql").and_then(move || async move {
+        match graphiql {
+            true => Ok(reply::html(
+                GraphiQLSource::build().endpoint("/v2/graphql").finish(),
+            )),
+            false => Err(warp::reject::not_found()),
+        }
+    });
+
+    // The method is checked first, so the handlers never run for other methods
+    warp::get()
+        .and(
+            meals_route
+                .or(next_meals_route)
+                .or(allergens_route)
+                .or(locations_route)
+                .or(nearby_locations_route)
+                .or(diagnostics_route)
+                .or(graphiql_route)
+                .or(openapi_route)
+                .or(docs_route)
+                .or(info_route),
+        )
+        .or(graphql_route)
+        .or(refresh_route)
+        .with(
+            warp::cors()
+                .allow_any_origin()
+                .allow_methods(["GET", "POST"])
+                .allow_header("content-type"),
+        )
+        .recover(APIError::handle_rejection)
 }
 
-fn with_state_and_query_filter<A, T: APIFilter<A> + 'static>(
-    state: State,
-) -> impl Filter<Extract = (impl APIFilter<A>, State), Error = warp::Rejection> + Clone {
-    warp::any().and(warp::query::<T>()).and(with_state(state))
+fn accept_language(
+) -> impl Filter<Extract = (Option<String>,), Error = std::convert::Infallible> + Clone {
+    warp::header::optional::<String>("accept-language")
+        .or(warp::any().map(|| None))
+        .unify()
+}
+
+/// Sets the Content-Language header to the negotiated languages
+fn with_content_language(reply: impl Reply, content_language: Option<String>) -> Response {
+    let mut response = reply.into_response();
+    if let Some(value) = content_language
+        .filter(|languages| !languages.is_empty())
+        .and_then(|languages| HeaderValue::from_str(&languages).ok())
+    {
+        response.headers_mut().insert(CONTENT_LANGUAGE, value);
+    }
+    response
+}
+
+/// Requires the admin token as bearer token. The route is not found, if no admin token is configured.
+fn with_admin_token(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                let Some(admin_token) = admin_token else {
+                    return Err(warp::reject::not_found());
+                };
+                match authorization
+                    .as_deref()
+                    .and_then(|a| a.strip_prefix("Bearer "))
+                {
+                    Some(token) if tokens_match(token, &admin_token) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Compares the SHA-256 digests of the tokens in constant time,
+/// so the response time does not reveal how much of the token was guessed right
+fn tokens_match(token: &str, admin_token: &str) -> bool {
+    Sha256::digest(token)
+        .iter()
+        .zip(Sha256::digest(admin_token).iter())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+fn with_refresher(
+    refresher: Arc<Refresher>,
+) -> impl Filter<Extract = (Arc<Refresher>,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || refresher.clone())
 }
 
 fn with_state(
@@ -156,18 +564,594 @@ fn with_state(
     warp::any().map(move || state.clone())
 }
 
-async fn default_handler<T: Serialize, F>(
-    query: impl APIFilter<T>,
+async fn default_handler<T: Serialize + Sortable, F>(
+    mut query: impl APIFilter<T>,
     state: State,
+    accept_language: Option<String>,
     data_fn: F,
-) -> Result<impl warp::Reply, warp::Rejection>
+) -> Result<Response, warp::Rejection>
 where
     F: Fn(&Data) -> &Vec<T>,
 {
-    let cache = state.read().await;
-    let data = cache.get_data().await.map_err(custom_reject)?;
-    Ok::<Json, warp::Rejection>(reply::json(&DefaultResponse {
-        last_updated: cache.get_last_update_as_string(),
-        data: query.filter(data_fn(data)),
-    }))
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let page = query.query(data_fn(&data)).map_err(bad_request)?;
+    let reply = reply::json(&DefaultResponse {
+        last_updated: data.get_last_updated().to_string(),
+        total: page.total,
+        data: page.items,
+    });
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+/// Get the allergens
+#[utoipa::path(
+    get,
+    path = "/v2/allergens",
+    params(
+        AllergensQuery,
+        (
+            "Accept-Language" = Option<String>,
+            Header,
+            description = "The preferred languages, used if no language is requested"
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The allergens and additives",
+            body = DefaultResponse<Allergen>,
+            headers(("Content-Language" = String, description = "The languages the response is served in"))
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+    )
+)]
+async fn allergens_handler(
+    mut query: AllergensQuery,
+    accept_language: Option<String>,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let page = query
+        .query_positions(data.get_allergens())
+        .map_err(bad_request)?;
+    let reply = reply::json(&DefaultResponse {
+        last_updated: data.get_last_updated().to_string(),
+        total: page.total,
+        data: page
+            .items
+            .into_iter()
+            .map(|i| data.allergen_json(i))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| custom_reject(anyhow!("Allergen not found in the cache")))?,
+    });
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+/// Get the problems found while parsing the menu pages. Requires the admin token.
+#[utoipa::path(
+    get,
+    path = "/v2/diagnostics",
+    params(DiagnosticsQuery),
+    responses(
+        (
+            status = 200,
+            description = "The warnings of the last scrape with the offending HTML",
+            body = DefaultResponse<ScrapeWarning>
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+async fn diagnostics_handler(
+    query: DiagnosticsQuery,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    default_handler(query, state, None, |d| d.get_warnings()).await
+}
+
+/// Scrape the data again without waiting for the next scheduled refresh. Requires the admin token.
+/// Joins a running refresh that covers the requested locations and weeks.
+#[utoipa::path(
+    post,
+    path = "/admin/refresh",
+    params(RefreshQuery),
+    responses(
+        (status = 200, description = "The refresh finished", body = RefreshReport),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or wrong admin token", body = ErrorResponse),
+        (status = 502, description = "The refresh failed", body = ErrorResponse),
+    ),
+    security(("admin_token" = []))
+)]
+async fn refresh_handler(
+    query: RefreshQuery,
+    refresher: Arc<Refresher>,
+) -> Result<Response, warp::Rejection> {
+    let scope = query.scope(refresher.get_scraper()).map_err(bad_request)?;
+    match refresher.refresh(scope).await {
+        Ok(report) => Ok(reply::json(&report).into_response()),
+        Err(e) => Err(warp::reject::custom(RefreshFailed(e))),
+    }
+}
+
+/// Get the meals
+#[utoipa::path(
+    get,
+    path = "/v2/meals",
+    params(
+        MealsQuery,
+        (
+            "Accept-Language" = Option<String>,
+            Header,
+            description = "The preferred languages, used if no language is requested"
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The meals. Grouped into `MealGroup`s if `group_by` is given",
+            body = DefaultResponse<MealView>,
+            headers(("Content-Language" = String, description = "The languages the response is served in"))
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+    )
+)]
+async fn meals_handler(
+    mut query: MealsQuery,
+    accept_language: Option<String>,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let mut positions = query.filter_meal_positions(&data);
+    query
+        .sort_meal_positions(data.get_meals(), &mut positions)
+        .map_err(bad_request)?;
+    let meals: Vec<&Meal> = positions.iter().map(|&i| &data.get_meals()[i]).collect();
+    let last_updated = data.last_updated_of(meals.iter().copied()).to_string();
+
+    let reply = match query.group_by() {
+        Some(group_by) => {
+            let days: Vec<_> = data
+                .get_days()
+                .iter()
+                .filter(|date| query.accepts_date(date))
+                .collect();
+            let locations: Vec<_> = data
+                .get_locations()
+                .iter()
+                .filter(|location| query.accepts_location(location))
+                .map(|location| data.localize(location, query.label_language()))
+                .collect();
+            let locations: Vec<_> = locations.iter().collect();
+            let groups = group_meals(
+                group_by,
+                &meals,
+                &days,
+                &locations,
+                &query.listing(&data),
+                query.price_category(),
+            );
+            let page = Page::new(groups, query.offset(), query.limit());
+            reply::json(&DefaultResponse {
+                last_updated,
+                total: page.total,
+                data: page.items,
+            })
+        }
+        None => {
+            let page = Page::new(positions, query.offset(), query.limit());
+            match query.price_category() {
+                Some(price_category) => reply::json(&DefaultResponse {
+                    last_updated,
+                    total: page.total,
+                    data: page
+                        .items
+                        .into_iter()
+                        .map(|i| MealView::new(&data.get_meals()[i], Some(price_category)))
+                        .collect(),
+                }),
+                // Without a price category the meals were serialized when the data was cached
+                None => reply::json(&DefaultResponse {
+                    last_updated,
+                    total: page.total,
+                    data: page
+                        .items
+                        .into_iter()
+                        .map(|i| data.meal_json(i))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| custom_reject(anyhow!("Meal not found in the cache")))?,
+                }),
+            }
+        }
+    };
+
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+/// Get the next dates a meal is served
+#[utoipa::path(
+    get,
+    path = "/v2/meals/next",
+    params(
+        NextMealsQuery,
+        (
+            "Accept-Language" = Option<String>,
+            Header,
+            description = "The preferred languages, used if no language is requested"
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The upcoming meals matching the search, ordered by date and location",
+            body = NextMealsResponse<MealView>,
+            headers(("Content-Language" = String, description = "The languages the response is served in"))
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+    )
+)]
+async fn next_meals_handler(
+    mut query: NextMealsQuery,
+    accept_language: Option<String>,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let page = query.query_meal_positions(&data).map_err(bad_request)?;
+    let meals = page.items.iter().map(|&i| &data.get_meals()[i]);
+    let reply = reply::json(&NextMealsResponse {
+        last_updated: data.last_updated_of(meals).to_string(),
+        total: page.total,
+        data: page
+            .items
+            .iter()
+            .map(|&i| data.meal_json(i))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| custom_reject(anyhow!("Meal not found in the cache")))?,
+        past_occurrences: state
+            .get_history()
+            .count_served(|entry| query.accepts_past(entry)),
+    });
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+/// Get the locations
+#[utoipa::path(
+    get,
+    path = "/v2/locations",
+    params(
+        LocationsQuery,
+        (
+            "Accept-Language" = Option<String>,
+            Header,
+            description = "The preferred languages, used if no language is requested"
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The locations",
+            body = DefaultResponse<LocationView>,
+            headers(("Content-Language" = String, description = "The languages the response is served in"))
+        ),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+    )
+)]
+async fn locations_handler(
+    mut query: LocationsQuery,
+    accept_language: Option<String>,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let page = query.query(data.get_locations()).map_err(bad_request)?;
+    let now = chrono::Utc::now().with_timezone(&TIME_ZONE);
+    let mut served_meals = vec![];
+    let locations: Vec<_> = page
+        .items
+        .into_iter()
+        .map(|location| {
+            let meals = query.meals_of(location, &data);
+            served_meals.extend(meals.iter().flatten().copied());
+            LocationView::new(data.localize(location, query.label_language()), now)
+                .with_distance(query.origin())
+                .with_meals(meals)
+        })
+        .collect();
+    let reply = reply::json(&DefaultResponse {
+        last_updated: data
+            .last_updated_of(served_meals)
+            .min(data.get_last_updated())
+            .to_string(),
+        total: page.total,
+        data: locations,
+    });
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+/// Get the locations sorted by their distance to a position
+#[utoipa::path(
+    get,
+    path = "/v2/locations/nearby",
+    params(
+        LocationsQuery,
+        (
+            "Accept-Language" = Option<String>,
+            Header,
+            description = "The preferred languages, used if no language is requested"
+        ),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The locations with known coordinates, sorted by their distance. `lat` and `lon` are required",
+            body = DefaultResponse<LocationView>,
+            headers(("Content-Language" = String, description = "The languages the response is served in"))
+        ),
+        (status = 400, description = "Invalid query parameters. `sort` is not supported, because the locations are sorted by distance", body = ErrorResponse),
+    )
+)]
+async fn nearby_locations_handler(
+    mut query: LocationsQuery,
+    accept_language: Option<String>,
+    state: State,
+) -> Result<Response, warp::Rejection> {
+    let origin = query
+        .origin()
+        .ok_or_else(|| bad_request(QueryError::new("Missing query parameters lat and lon")))?;
+    if query.sort().is_some() {
+        return Err(bad_request(QueryError::new(
+            "The nearby locations are sorted by distance and do not support sort",
+        )));
+    }
+    let data = state.get_data().map_err(custom_reject)?;
+    query.negotiate_languages(accept_language.as_deref(), &data);
+    let now = chrono::Utc::now().with_timezone(&TIME_ZONE);
+
+    let mut served_meals = vec![];
+    let mut locations: Vec<_> = query
+        .filter(data.get_locations())
+        .into_iter()
+        .filter(|location| location.get_coordinates().is_some())
+        .map(|location| {
+            let meals = query.meals_of(location, &data);
+            served_meals.extend(meals.iter().flatten().copied());
+            LocationView::new(data.localize(location, query.label_language()), now)
+                .with_distance(Some(origin))
+                .with_meals(meals)
+        })
+        .collect();
+    locations.sort_by(|a, b| {
+        a.distance()
+            .unwrap_or(f64::MAX)
+            .total_cmp(&b.distance().unwrap_or(f64::MAX))
+    });
+    let page = Page::new(locations, query.offset(), query.limit());
+
+    let reply = reply::json(&DefaultResponse {
+        last_updated: data
+            .last_updated_of(served_meals)
+            .min(data.get_last_updated())
+            .to_string(),
+        total: page.total,
+        data: page.items,
+    });
+    Ok(with_content_language(reply, query.content_language()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashMap};
+
+    use serde::de::DeserializeOwned;
+    use utoipa::openapi::path::ParameterIn;
+
+    use super::*;
+
+    fn test_routes(
+        state: State,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone {
+        let config = Config::init_from_hashmap(&HashMap::from([(
+            "ADMIN_TOKEN".to_string(),
+            "secret".to_string(),
+        )]))
+        .unwrap();
+        let scraper = Scraper::new(
+            Locations::load(None).unwrap(),
+            Languages::load(None).unwrap(),
+        );
+        let refresher = Arc::new(Refresher::new(scraper, state.clone(), None));
+        routes(&config, state, refresher)
+    }
+
+    /// The documented operations as method and path
+    fn operations() -> Vec<(&'static str, String, utoipa::openapi::path::Operation)> {
+        ApiDoc::openapi()
+            .paths
+            .paths
+            .into_iter()
+            .flat_map(|(path, item)| {
+                [("GET", item.get), ("POST", item.post), ("PUT", item.put)]
+                    .into_iter()
+                    .chain([("DELETE", item.delete), ("PATCH", item.patch)])
+                    .filter_map(move |(method, operation)| {
+                        operation.map(|operation| (method, path.clone(), operation))
+                    })
+            })
+            .collect()
+    }
+
+    /// The query parameters warp accepts for the query, i.e. its fields as deserialized by serde
+    fn fields<Q: DeserializeOwned + Serialize>(query: serde_json::Value) -> BTreeSet<String> {
+        let query: Q = serde_json::from_value(query).unwrap();
+        match serde_json::to_value(query).unwrap() {
+            serde_json::Value::Object(fields) => fields.keys().cloned().collect(),
+            _ => unreachable!("queries are structs"),
+        }
+    }
+
+    #[test]
+    fn documents_the_mounted_routes() {
+        let documented: BTreeSet<_> = operations()
+            .into_iter()
+            .map(|(method, path, _)| (method, path))
+            .collect();
+        let expected = BTreeSet::from([
+            ("GET", "/v2/meals".to_string()),
+            ("GET", "/v2/meals/next".to_string()),
+            ("GET", "/v2/allergens".to_string()),
+            ("GET", "/v2/locations".to_string()),
+            ("GET", "/v2/locations/nearby".to_string()),
+            ("GET", "/v2/diagnostics".to_string()),
+            ("POST", "/admin/refresh".to_string()),
+        ]);
+        assert_eq!(documented, expected);
+
+        let routes = test_routes(Arc::new(Cache::new(chrono::Duration::minutes(1)).unwrap()));
+        for (method, path) in documented {
+            let status = |method| {
+                let response = futures::executor::block_on(
+                    warp::test::request()
+                        .method(method)
+                        .path(&path)
+                        .reply(&routes),
+                );
+                response.status()
+            };
+            let status_of_documented = status(method);
+            assert_ne!(
+                status_of_documented,
+                StatusCode::NOT_FOUND,
+                "{method} {path}"
+            );
+            assert_ne!(
+                status_of_documented,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {path}"
+            );
+            let other_method = match method {
+                "GET" => "POST",
+                _ => "GET",
+            };
+            assert_eq!(
+                status(other_method),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{other_method} {path}"
+            );
+        }
+    }
+
+    #[test]
+    fn documents_the_query_parameters() {
+        let empty = serde_json::json!({});
+        let expected = HashMap::from([
+            ("/v2/meals", fields::<MealsQuery>(empty.clone())),
+            (
+                "/v2/meals/next",
+                fields::<NextMealsQuery>(serde_json::json!({ "q": "Pasta" })),
+            ),
+            ("/v2/allergens", fields::<AllergensQuery>(empty.clone())),
+            ("/v2/locations", fields::<LocationsQuery>(empty.clone())),
+            (
+                "/v2/locations/nearby",
+                fields::<LocationsQuery>(empty.clone()),
+            ),
+            ("/v2/diagnostics", fields::<DiagnosticsQuery>(empty.clone())),
+            ("/admin/refresh", fields::<RefreshQuery>(empty)),
+        ]);
+
+        for (method, path, operation) in operations() {
+            let documented: BTreeSet<_> = operation
+                .parameters
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|parameter| parameter.parameter_in == ParameterIn::Query)
+                .map(|parameter| parameter.name)
+                .collect();
+            assert_eq!(documented, expected[path.as_str()], "{method} {path}");
+        }
+    }
+
+    #[test]
+    fn matches_only_the_admin_token() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret2", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn tells_whether_the_nearby_locations_are_open() {
+        let state = Arc::new(Cache::new(chrono::Duration::minutes(1)).unwrap());
+        state
+            .set_data(mensa_api::fixtures::data("nearby").await)
+            .unwrap();
+        let response = warp::test::request()
+            .path("/v2/locations/nearby?lat=53.87&lon=10.68")
+            .reply(&test_routes(state))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let locations = body["data"].as_array().unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0]["code"], "HL_MH");
+        assert!(locations[0]["open_now"].is_boolean());
+    }
+
+    /// The client parses the responses of the server into the same data the server holds
+    #[cfg(feature = "client")]
+    #[tokio::test]
+    async fn client_reads_the_server_responses() {
+        use mensa_api::client::MensaClient;
+
+        let data = mensa_api::fixtures::data("client").await;
+        assert_eq!(data.get_meals().len(), 10);
+        let state = Arc::new(Cache::new(chrono::Duration::minutes(1)).unwrap());
+        state.set_data(data.clone()).unwrap();
+        let (address, server) = warp::serve(test_routes(state)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        let client = MensaClient::new(format!("http://{address}"));
+
+        // The locations nested into meals carry only code, name and city
+        let nested = |meal: &Meal| {
+            let location = meal.get_location();
+            (
+                meal.get_name().to_string(),
+                meal.get_date().to_string(),
+                meal.get_price().clone(),
+                meal.is_vegan(),
+                meal.is_vegetarian(),
+                (
+                    location.get_code().to_string(),
+                    location.get_name().to_string(),
+                    location.get_city().to_string(),
+                ),
+                meal.get_allergens().clone(),
+                meal.get_language().clone(),
+            )
+        };
+        let meals = client
+            .meals(&MealsQuery::default().with_languages(["de", "en"]))
+            .await
+            .unwrap();
+        assert_eq!(meals.total, 10);
+        assert_eq!(
+            meals.data.iter().map(nested).collect::<Vec<_>>(),
+            data.get_meals().iter().map(nested).collect::<Vec<_>>()
+        );
+
+        let allergens = client
+            .allergens(&AllergensQuery::default().with_languages(["de", "en"]))
+            .await
+            .unwrap();
+        assert_eq!(&allergens.data, data.get_allergens());
+
+        let locations = client.locations(&LocationsQuery::default()).await.unwrap();
+        assert_eq!(&locations.data, data.get_locations());
+    }
 }
@@ -1,37 +1,234 @@
+use std::collections::HashMap;
+
 use serde::ser::SerializeSeq;
-use serde::ser::SerializeStruct;
+use serde::Deserialize;
 use serde::Serialize;
 use serde::Serializer;
+use utoipa::ToSchema;
 
-use crate::scrapers::{scrape_allergens, scrape_meals};
+#[cfg(feature = "server")]
+use crate::archive::{PageFetcher, PageSource, Snapshot};
+use crate::diagnostics::ScrapeWarning;
+use crate::languages::{LanguageConfig, Languages};
+use crate::opening_hours::{OpeningHours, TIME_ZONE};
+#[cfg(feature = "server")]
+use crate::refresh::RefreshScope;
+#[cfg(feature = "server")]
+use crate::scrapers::{scrape_opening_hours, Scraper};
 use anyhow::anyhow;
-use strum::EnumIter;
-use strum::IntoEnumIterator;
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+#[cfg(feature = "server")]
+use futures::future::join_all;
+use itertools::Itertools;
 
 #[derive(Debug, Clone)]
 pub struct Data {
     allergens: Vec<Allergen>,
     meals: Vec<Meal>,
     locations: Vec<APILocation>,
+    /// All days for which a menu has been published, even if no meal is served on some of them
+    days: Vec<String>,
+    languages: Languages,
+    /// The problems found while parsing the menu pages
+    warnings: Vec<ScrapeWarning>,
+    /// The freshness of every scraped menu page
+    slices: Vec<Slice>,
+    /// When the allergens and locations were scraped
+    last_updated: DateTime<Utc>,
+    index: MealIndex,
+}
+
+/// The positions of the meals by date, location code and language code, in ascending order
+#[derive(Debug, Clone, Default)]
+struct MealIndex {
+    by_date: HashMap<String, Vec<usize>>,
+    by_location: HashMap<String, Vec<usize>>,
+    by_language: HashMap<String, Vec<usize>>,
 }
 
 impl Data {
-    pub(crate) async fn fetch() -> anyhow::Result<Data> {
-        let locations: Vec<APILocation> = Location::iter().map(|l| l.into()).collect();
-        let allergens = scrape_allergens().await?;
-        let meals = scrape_meals(&allergens).await?;
+    /// Scrapes all data. All pages of the scrape are archived as one snapshot, even if the scrape fails.
+    #[cfg(feature = "server")]
+    pub async fn fetch(scraper: &Scraper) -> anyhow::Result<Data> {
+        let pages = PageFetcher::new(scraper.get_source(), scraper.get_page_cache());
+        let data = Self::scrape(scraper, &pages).await;
+        pages
+            .finish(scraper.get_weeks(), None, data.as_ref().err())
+            .await;
+        data
+    }
+
+    /// Scrapes the data of an archived snapshot again.
+    /// The pages of a partial refresh are replayed on top of the full scrape they refreshed.
+    #[cfg(feature = "server")]
+    pub async fn replay(scraper: &Scraper, snapshot: &Snapshot) -> anyhow::Result<Data> {
+        let mut chain = snapshot.replay_chain()?.into_iter();
+        let full = chain.next().ok_or(anyhow!("Nothing to replay"))?;
+        let mut data = scraper
+            .clone()
+            .with_source(PageSource::Replay(full))
+            .fetch()
+            .await?;
+        for refresh in chain {
+            let scope = refresh.get_scope().cloned().unwrap_or_default();
+            let scraper = scraper.clone().with_source(PageSource::Replay(refresh));
+            data = data.refresh(&scraper, &scope).await?;
+        }
+        Ok(data)
+    }
+
+    #[cfg(feature = "server")]
+    async fn scrape(scraper: &Scraper, pages: &PageFetcher<'_>) -> anyhow::Result<Data> {
+        let languages = scraper.get_languages();
+        let allergens = scraper.scrape_allergens_from(pages).await?;
+        let menu = scraper.scrape_meals_from(pages, &allergens).await?;
+        let locations = join_all(scraper.get_locations().iter().map(|location| async {
+            let mut api_location: APILocation = location.into();
+            api_location.available_languages =
+                available_languages(languages, &menu.meals, &location.code);
+            if let Some(source) = &location.opening_hours_source {
+                match scrape_opening_hours(source, pages).await {
+                    Ok(scraped) if !scraped.regular.is_empty() => {
+                        let opening_hours = &mut api_location.opening_hours;
+                        opening_hours.regular = scraped.regular;
+                        // Configured exceptions take precedence over the scraped ones
+                        let scraped_exceptions: Vec<_> = scraped
+                            .exceptions
+                            .into_iter()
+                            .filter(|scraped| {
+                                !opening_hours
+                                    .exceptions
+                                    .iter()
+                                    .any(|e| e.date == scraped.date)
+                            })
+                            .collect();
+                        opening_hours.exceptions.extend(scraped_exceptions);
+                    }
+                    Ok(_) => eprintln!("No opening hours found for {}", location.code),
+                    Err(e) => eprintln!("Failed to scrape opening hours of {}: {e}", location.code),
+                }
+            }
+            api_location
+        }))
+        .await;
 
         Ok(Self {
             locations,
             allergens,
-            meals,
+            index: MealIndex::new(&menu.meals),
+            meals: menu.meals,
+            days: menu.days,
+            languages: languages.clone(),
+            warnings: menu.warnings,
+            slices: menu.slices,
+            last_updated: Utc::now(),
         })
     }
 
+    /// Scrapes the slices in the scope again and replaces their meals.
+    /// Locations sharing a page with a location of the scope are refreshed as well.
+    /// Allergens and opening hours are kept, as are the slices whose page failed.
+    /// The pages are archived as a snapshot with the scope.
+    #[cfg(feature = "server")]
+    pub async fn refresh(&self, scraper: &Scraper, scope: &RefreshScope) -> anyhow::Result<Data> {
+        let pages = PageFetcher::new(scraper.get_source(), scraper.get_page_cache());
+        let menu = scraper
+            .scrape_meals_of(&pages, &self.allergens, scope)
+            .await;
+        pages
+            .finish(scraper.get_weeks(), Some(scope), menu.as_ref().err())
+            .await;
+        let menu = menu?;
+
+        let mut data = self.clone();
+        for slice in &menu.slices {
+            // The days the page listed before go as well, so a day the website dropped loses its meals
+            let previous = data.slices.iter().find(|old| old.is_same(slice)).cloned();
+            data.meals.retain(|meal| {
+                !slice.contains(meal) && !previous.as_ref().is_some_and(|old| old.contains(meal))
+            });
+            // Keyed by date, as the relative week of older warnings changes with the week
+            data.warnings.retain(|warning| {
+                !(warning.location == slice.location
+                    && warning.language == slice.language
+                    && warning.week_start == slice.week_start)
+            });
+            data.slices.retain(|old| !old.is_same(slice));
+        }
+        data.meals.extend(menu.meals);
+        data.warnings.extend(menu.warnings);
+        data.slices.extend(menu.slices);
+        data.days = data
+            .slices
+            .iter()
+            .flat_map(|slice| slice.days.iter().cloned())
+            .sorted()
+            .dedup()
+            .collect();
+        for location in &mut data.locations {
+            location.available_languages =
+                available_languages(&data.languages, &data.meals, &location.code);
+        }
+        data.index = MealIndex::new(&data.meals);
+
+        Ok(data)
+    }
+
     pub fn get_meals(&self) -> &Vec<Meal> {
         &self.meals
     }
 
+    /// The meals on one of the dates, at one of the locations and in one of the languages, in the order of the meals.
+    /// Looked up in the index, so the other meals are not visited. `None` accepts every date, location or language.
+    pub fn find_meals(
+        &self,
+        dates: Option<&[String]>,
+        locations: Option<&[String]>,
+        languages: Option<&[String]>,
+    ) -> Vec<&Meal> {
+        self.find_meal_positions(dates, locations, languages)
+            .into_iter()
+            .map(|position| &self.meals[position])
+            .collect()
+    }
+
+    /// The positions of the meals [`Data::find_meals`] returns in the meals of the data
+    pub fn find_meal_positions(
+        &self,
+        dates: Option<&[String]>,
+        locations: Option<&[String]>,
+        languages: Option<&[String]>,
+    ) -> Vec<usize> {
+        let lookups = [
+            (&self.index.by_date, dates),
+            (&self.index.by_location, locations),
+            (&self.index.by_language, languages),
+        ];
+        let mut positions: Option<Vec<usize>> = None;
+        for (index, keys) in lookups {
+            let Some(keys) = keys else {
+                continue;
+            };
+            let found: Vec<usize> = keys
+                .iter()
+                .filter_map(|key| index.get(key))
+                .kmerge()
+                .dedup()
+                .copied()
+                .collect();
+            positions = Some(match positions {
+                Some(positions) => positions
+                    .into_iter()
+                    .filter(|position| found.binary_search(position).is_ok())
+                    .collect(),
+                None => found,
+            });
+        }
+
+        positions.unwrap_or_else(|| (0..self.meals.len()).collect())
+    }
+
     pub fn get_allergens(&self) -> &Vec<Allergen> {
         &self.allergens
     }
@@ -39,36 +236,352 @@ impl Data {
     pub fn get_locations(&self) -> &Vec<APILocation> {
         &self.locations
     }
+
+    pub fn get_days(&self) -> &Vec<String> {
+        &self.days
+    }
+
+    pub fn get_languages(&self) -> &Languages {
+        &self.languages
+    }
+
+    pub fn get_warnings(&self) -> &Vec<ScrapeWarning> {
+        &self.warnings
+    }
+
+    /// The freshness of every scraped menu page
+    pub fn get_slices(&self) -> &Vec<Slice> {
+        &self.slices
+    }
+
+    /// When the allergens and locations were scraped
+    pub fn get_last_updated(&self) -> DateTime<Utc> {
+        self.last_updated
+    }
+
+    /// When the oldest slice the meals were scraped from was updated.
+    /// When the allergens and locations were scraped, if there are no meals.
+    pub fn last_updated_of<'a>(&self, meals: impl IntoIterator<Item = &'a Meal>) -> DateTime<Utc> {
+        meals
+            .into_iter()
+            .map(|meal| (&meal.location.code, &meal.language.code, &meal.date))
+            .unique()
+            .filter_map(|(location, language, date)| {
+                self.slices
+                    .iter()
+                    .find(|slice| {
+                        &slice.language == language
+                            && slice.locations.contains(location)
+                            && slice.days.contains(date)
+                    })
+                    .map(|slice| slice.last_updated)
+            })
+            .min()
+            .unwrap_or(self.last_updated)
+    }
+
+    /// The location with its labels in the given language, or in german if the language is unknown
+    pub fn localize(&self, location: &APILocation, language_code: &str) -> APILocation {
+        match self.languages.get(language_code) {
+            Some(language) => location.localized(language),
+            None => location.clone(),
+        }
+    }
+}
+
+/// The meals of one menu page in one week and language, which are scraped and refreshed together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice {
+    /// The code of the location whose page was scraped
+    pub(crate) location: String,
+    /// The codes of the locations listed on the page
+    pub(crate) locations: Vec<String>,
+    /// The monday of the week
+    pub(crate) week_start: NaiveDate,
+    pub(crate) language: String,
+    /// The days listed on the page
+    pub(crate) days: Vec<String>,
+    pub(crate) last_updated: DateTime<Utc>,
+}
+
+impl Slice {
+    pub fn get_location(&self) -> &str {
+        &self.location
+    }
+
+    /// The codes of the locations listed on the page of the slice
+    pub fn get_locations(&self) -> &Vec<String> {
+        &self.locations
+    }
+
+    pub fn get_week_start(&self) -> NaiveDate {
+        self.week_start
+    }
+
+    /// The week relative to the current week. `None` if the week is over.
+    pub fn get_week(&self) -> Option<usize> {
+        let weeks = (self.week_start - week_start(0)).num_weeks();
+        usize::try_from(weeks).ok()
+    }
+
+    pub fn get_language(&self) -> &str {
+        &self.language
+    }
+
+    pub fn get_last_updated(&self) -> DateTime<Utc> {
+        self.last_updated
+    }
+
+    /// Whether the page lists the day for the location
+    pub fn lists(&self, location_code: &str, date: &str) -> bool {
+        self.locations.iter().any(|code| code == location_code)
+            && self.days.iter().any(|day| day == date)
+    }
+
+    #[cfg(feature = "server")]
+    fn contains(&self, meal: &Meal) -> bool {
+        self.language == meal.language.code
+            && self.locations.contains(&meal.location.code)
+            && self.days.contains(&meal.date)
+    }
+
+    /// Whether both slices are of the same page, week and language
+    #[cfg(feature = "server")]
+    fn is_same(&self, other: &Slice) -> bool {
+        self.location == other.location
+            && self.week_start == other.week_start
+            && self.language == other.language
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl MealIndex {
+    #[cfg(feature = "server")]
+    fn new(meals: &[Meal]) -> Self {
+        let mut index = Self::default();
+        for (position, meal) in meals.iter().enumerate() {
+            index
+                .by_date
+                .entry(meal.date.clone())
+                .or_default()
+                .push(position);
+            index
+                .by_location
+                .entry(meal.location.code.clone())
+                .or_default()
+                .push(position);
+            index
+                .by_language
+                .entry(meal.language.code.clone())
+                .or_default()
+                .push(position);
+        }
+        index
+    }
+}
+
+/// The monday of the given week, 0 being the current week
+pub(crate) fn week_start(week: usize) -> NaiveDate {
+    let today = Utc::now().with_timezone(&TIME_ZONE).date_naive();
+    today - Days::new(today.weekday().num_days_from_monday() as u64) + Days::new(7 * week as u64)
+}
+
+/// A location is available in every language the website lists meals for it in
+#[cfg(feature = "server")]
+fn available_languages(
+    languages: &Languages,
+    meals: &[Meal],
+    location_code: &str,
+) -> Vec<Language> {
+    languages
+        .iter()
+        .filter(|language| {
+            meals.iter().any(|meal| {
+                meal.location.code == location_code && meal.language.code == language.code
+            })
+        })
+        .map(|language| language.language())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub struct Allergen {
     pub(crate) code: String,
     pub(crate) name: String,
+    /// Missing in the allergens nested into meals, which are in the language of the meal
+    #[serde(default)]
     pub(crate) language: Language,
 }
 
 impl Allergen {
-    pub fn alkohol(language_code: &str) -> Self {
-        let language = if language_code == "en" {
-            Language::english()
-        } else {
-            Language::german()
-        };
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
 
-        Allergen { code: "alko".to_owned(), name: "Alkohol".to_owned(), language }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_language(&self) -> &Language {
+        &self.language
+    }
+
+    pub fn alkohol(language: &LanguageConfig) -> Self {
+        Allergen {
+            code: "alko".to_owned(),
+            name: language
+                .allergen_name("alko")
+                .unwrap_or("Alkohol")
+                .to_owned(),
+            language: language.language(),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// The details besides code, name and city are missing in the locations nested into meals
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct APILocation {
     pub(crate) code: String,
     pub(crate) name: String,
     pub(crate) city: String,
+    #[serde(default)]
     pub(crate) available_languages: Vec<Language>,
+    #[serde(default)]
+    pub(crate) address: Option<String>,
+    #[serde(default)]
+    pub(crate) coordinates: Option<Coordinates>,
+    #[serde(default)]
+    pub(crate) opening_hours: OpeningHours,
+    /// The menu of the location on the studentenwerk.sh website
+    #[serde(default)]
+    pub(crate) url: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl APILocation {
+    pub fn get_code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_city(&self) -> &str {
+        &self.city
+    }
+
+    pub fn get_available_languages(&self) -> &Vec<Language> {
+        &self.available_languages
+    }
+
+    pub fn get_address(&self) -> Option<&str> {
+        self.address.as_deref()
+    }
+
+    pub fn get_coordinates(&self) -> Option<Coordinates> {
+        self.coordinates
+    }
+
+    pub fn get_opening_hours(&self) -> &OpeningHours {
+        &self.opening_hours
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    /// The location with its name and city translated into the language
+    pub fn localized(&self, language: &LanguageConfig) -> Self {
+        Self {
+            name: language
+                .location_name(&self.code)
+                .map_or_else(|| self.name.clone(), |name| name.to_string()),
+            city: language
+                .location_city(&self.code)
+                .map_or_else(|| self.city.clone(), |city| city.to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Coordinates {
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+}
+
+impl Coordinates {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    pub fn get_lat(&self) -> f64 {
+        self.lat
+    }
+
+    pub fn get_lon(&self) -> f64 {
+        self.lon
+    }
+
+    /// The great-circle distance in kilometers
+    pub fn distance_to(&self, other: &Coordinates) -> f64 {
+        const EARTH_RADIUS: f64 = 6371.0;
+
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+/// The serialized form of a location in an API response
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LocationView<'a> {
+    #[serde(flatten)]
+    location: APILocation,
+    /// `None` if the opening hours of the location are unknown
+    open_now: Option<bool>,
+    /// The distance in kilometers to the requested position
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meals: Option<Vec<MealView<'a>>>,
+}
+
+impl<'a> LocationView<'a> {
+    pub fn new(location: APILocation, now: DateTime<Tz>) -> Self {
+        Self {
+            open_now: location.opening_hours.is_open(now),
+            location,
+            distance: None,
+            meals: None,
+        }
+    }
+
+    /// Adds the distance to the origin, if the coordinates of the location are known
+    pub fn with_distance(mut self, origin: Option<Coordinates>) -> Self {
+        self.distance = origin
+            .zip(self.location.coordinates)
+            .map(|(o, c)| o.distance_to(&c));
+        self
+    }
+
+    pub fn with_meals(mut self, meals: Option<Vec<&'a Meal>>) -> Self {
+        self.meals = meals.map(|meals| {
+            meals
+                .into_iter()
+                .map(|meal| MealView::new(meal, None))
+                .collect()
+        });
+        self
+    }
+
+    pub fn distance(&self) -> Option<f64> {
+        self.distance
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Meal {
     pub(crate) name: String,
     pub(crate) date: String,
@@ -76,24 +589,97 @@ pub struct Meal {
     pub(crate) vegan: bool,
     pub(crate) vegetarian: bool,
     #[serde(serialize_with = "serialize_nested_location")]
+    #[schema(value_type = NestedLocation)]
     pub(crate) location: APILocation,
     #[serde(serialize_with = "serialize_nested_allergen")]
+    #[schema(value_type = Vec<NestedAllergen>)]
     pub(crate) allergens: Vec<Allergen>,
     pub(crate) language: Language,
 }
 
+impl Meal {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The date in the format YYYY-MM-DD
+    pub fn get_date(&self) -> &str {
+        &self.date
+    }
+
+    pub fn get_price(&self) -> &Prices {
+        &self.price
+    }
+
+    pub fn is_vegan(&self) -> bool {
+        self.vegan
+    }
+
+    pub fn is_vegetarian(&self) -> bool {
+        self.vegetarian
+    }
+
+    /// The location in the language of the meal. Opening hours are not scraped for it.
+    pub fn get_location(&self) -> &APILocation {
+        &self.location
+    }
+
+    pub fn get_allergens(&self) -> &Vec<Allergen> {
+        &self.allergens
+    }
+
+    pub fn get_language(&self) -> &Language {
+        &self.language
+    }
+}
+
+/// The serialized form of a meal in an API response.
+/// Carries the flat price of the requested price category next to the full price information.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MealView<'a> {
+    #[serde(flatten)]
+    meal: &'a Meal,
+    /// The price in the requested price category. Named `category_price`, because `price` already holds
+    /// the prices of all categories and renaming it would break existing clients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category_price: Option<f32>,
+}
+
+impl<'a> MealView<'a> {
+    pub fn new(meal: &'a Meal, price_category: Option<PriceCategory>) -> Self {
+        Self {
+            meal,
+            category_price: price_category.map(|c| meal.price.get(c)),
+        }
+    }
+}
+
 fn serialize_nested_location<S>(nested: &APILocation, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let mut state = serializer.serialize_struct("APILocation", 2)?;
-    state.serialize_field("code", &nested.code)?;
-    state.serialize_field("name", &nested.name)?;
-    state.serialize_field("city", &nested.city)?;
-    state.end()
+    NestedLocation::from(nested).serialize(serializer)
 }
 
-fn serialize_nested_allergen<S>(nested: &Vec<Allergen>, serializer: S) -> Result<S::Ok, S::Error>
+/// The short form of a location used when it is embedded into other objects
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NestedLocation<'a> {
+    code: &'a str,
+    name: &'a str,
+    city: &'a str,
+}
+
+impl<'a> From<&'a APILocation> for NestedLocation<'a> {
+    fn from(value: &'a APILocation) -> Self {
+        Self {
+            code: &value.code,
+            name: &value.name,
+            city: &value.city,
+        }
+    }
+}
+
+fn serialize_nested_allergen<S>(nested: &[Allergen], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
@@ -105,7 +691,7 @@ where
     state.end()
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 struct NestedAllergen<'a> {
     code: &'a str,
     name: &'a str,
@@ -120,7 +706,7 @@ impl<'a> From<&'a Allergen> for NestedAllergen<'a> {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
 pub struct Language {
     /// The native name of the language
     pub(crate) name: String,
@@ -129,35 +715,47 @@ pub struct Language {
 }
 
 impl Language {
-    pub(crate) fn german() -> Self {
-        Self {
-            name: "Deutsch".to_owned(),
-            code: "de".to_owned(),
-        }
+    pub fn get_name(&self) -> &str {
+        &self.name
     }
-    pub(crate) fn english() -> Self {
-        Self {
-            name: "English".to_owned(),
-            code: "en".to_owned(),
-        }
+
+    pub fn get_code(&self) -> &str {
+        &self.code
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct Prices {
     students: f32,
     employees: f32,
     guests: f32,
 }
 
-impl Default for Prices {
-    fn default() -> Self {
-        Self {
-            students: Default::default(),
-            employees: Default::default(),
-            guests: Default::default(),
+impl Prices {
+    pub fn get(&self, category: PriceCategory) -> f32 {
+        match category {
+            PriceCategory::Students => self.students,
+            PriceCategory::Employees => self.employees,
+            PriceCategory::Guests => self.guests,
         }
     }
+
+    /// The price in the category. `None` if the page listed no price that could be parsed, which is stored as 0.
+    pub fn get_listed(&self, category: PriceCategory) -> Option<f32> {
+        let price = self.get(category);
+        (price > 0.0).then_some(price)
+    }
+}
+
+/// The group of people a price applies to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[cfg_attr(feature = "server", derive(async_graphql::Enum))]
+#[serde(rename_all = "lowercase")]
+pub enum PriceCategory {
+    #[default]
+    Students,
+    Employees,
+    Guests,
 }
 
 impl TryFrom<String> for Prices {
@@ -182,244 +780,193 @@ impl TryFrom<String> for Prices {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
-pub enum Location {
-    LuebeckMusikhochschule,
-    LuebeckCafeteria,
-    LuebeckMensa,
-    LuebeckBitsBytes,
-    KielMensa1,
-    KielCafeteria1,
-    KielMensa2,
-    KielCafeteria2,
-    KielMensaKesselhaus,
-    KielSchwentine,
-    KielDiner,
-    KielDockside,
-    HeideMensa,
-    FlensburgMensa,
-    FlensburgCafeteriaA,
-    FlensburgCafeteriaB,
-    OsterroenfeldMensa,
-    WedelCafeteria,
-}
-
-impl Location {
-    /// The speiseplan website uses number codes to differentiate between locations.
-    /// This method translates the location into these codes.
-    pub(crate) fn get_mensa_code(&self) -> usize {
-        match self {
-            Location::LuebeckMusikhochschule => 9,
-            Location::LuebeckCafeteria => 8,
-            Location::LuebeckMensa => 8,
-            Location::KielMensa1 => 1,
-            Location::KielCafeteria1 => 1,
-            Location::KielMensa2 => 2,
-            Location::KielCafeteria2 => 2,
-            Location::KielMensaKesselhaus => 4,
-            Location::KielSchwentine => 5,
-            Location::KielDiner => 6,
-            Location::KielDockside => 16,
-            Location::HeideMensa => 16,
-            Location::FlensburgMensa => 7,
-            Location::FlensburgCafeteriaA => 7,
-            Location::FlensburgCafeteriaB => 14,
-            Location::OsterroenfeldMensa => 14,
-            Location::WedelCafeteria => 15,
-            Location::LuebeckBitsBytes => 17,
-        }
-    }
-    /// The speiseplan website uses number codes to differentiate between locations.
-    /// This method translates the location into these codes.
-    pub(crate) fn get_ort_code(&self) -> usize {
-        match self {
-            Location::LuebeckMusikhochschule => 3,
-            Location::LuebeckCafeteria => 3,
-            Location::LuebeckMensa => 3,
-            Location::KielMensa1 => 1,
-            Location::KielCafeteria1 => 1,
-            Location::KielMensa2 => 1,
-            Location::KielCafeteria2 => 1,
-            Location::KielMensaKesselhaus => 1,
-            Location::KielSchwentine => 1,
-            Location::KielDiner => 1,
-            Location::KielDockside => 1,
-            Location::HeideMensa => 4,
-            Location::FlensburgMensa => 2,
-            Location::FlensburgCafeteriaA => 2,
-            Location::FlensburgCafeteriaB => 2,
-            Location::OsterroenfeldMensa => 6,
-            Location::WedelCafeteria => 5,
-            Location::LuebeckBitsBytes => 3,
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use crate::config::Weeks;
+    use crate::fixtures::{self, FixtureMeal};
+
+    fn meal<'a>(date: &'a str, name: &'a str) -> FixtureMeal<'a> {
+        FixtureMeal {
+            date,
+            name,
+            art: "",
+            allergens: "Gl",
+            arten: "vn",
+            price: "3,50 € / 5,00 € / 6,50 €",
         }
     }
 
-    pub(crate) fn get_cafeteria_option(&self) -> Self {
-        match self {
-            Location::LuebeckMusikhochschule => Location::LuebeckMusikhochschule,
-            Location::LuebeckCafeteria => Self::LuebeckCafeteria,
-            Location::LuebeckMensa => Self::LuebeckCafeteria,
-            Location::KielMensa1 => Self::KielCafeteria1,
-            Location::KielCafeteria1 => Self::KielCafeteria1,
-            Location::KielMensa2 => Self::KielCafeteria2,
-            Location::KielCafeteria2 => Self::KielCafeteria2,
-            Location::KielMensaKesselhaus => Self::KielMensaKesselhaus,
-            Location::KielSchwentine => Self::KielSchwentine,
-            Location::KielDiner => Self::KielDiner,
-            Location::KielDockside => Self::KielDockside,
-            Location::HeideMensa => Self::HeideMensa,
-            Location::FlensburgMensa => Self::FlensburgCafeteriaA,
-            Location::FlensburgCafeteriaA => Self::FlensburgCafeteriaA,
-            Location::FlensburgCafeteriaB => Self::FlensburgCafeteriaB,
-            Location::OsterroenfeldMensa => Self::OsterroenfeldMensa,
-            Location::WedelCafeteria => Self::WedelCafeteria,
-            Location::LuebeckBitsBytes => Self::LuebeckBitsBytes,
-        }
+    fn names_on<'a>(data: &'a Data, date: &str, language: &str) -> Vec<&'a str> {
+        data.get_meals()
+            .iter()
+            .filter(|meal| meal.date == date && meal.language.code == language)
+            .map(|meal| meal.name.as_str())
+            .collect()
     }
 
-    pub(crate) fn get_mensa_option(&self) -> Self {
-        match self {
-            Location::LuebeckMusikhochschule => Location::LuebeckMusikhochschule,
-            Location::LuebeckCafeteria => Self::LuebeckMensa,
-            Location::LuebeckMensa => Self::LuebeckMensa,
-            Location::KielMensa1 => Self::KielMensa1,
-            Location::KielCafeteria1 => Self::KielMensa1,
-            Location::KielMensa2 => Self::KielMensa2,
-            Location::KielCafeteria2 => Self::KielMensa2,
-            Location::KielMensaKesselhaus => Self::KielMensaKesselhaus,
-            Location::KielSchwentine => Self::KielSchwentine,
-            Location::KielDiner => Self::KielDiner,
-            Location::KielDockside => Self::KielDockside,
-            Location::HeideMensa => Self::HeideMensa,
-            Location::FlensburgMensa => Self::FlensburgMensa,
-            Location::FlensburgCafeteriaA => Self::FlensburgMensa,
-            Location::FlensburgCafeteriaB => Self::FlensburgCafeteriaB,
-            Location::OsterroenfeldMensa => Self::OsterroenfeldMensa,
-            Location::WedelCafeteria => Self::WedelCafeteria,
-            Location::LuebeckBitsBytes => Self::LuebeckBitsBytes,
-        }
+    #[tokio::test]
+    async fn refresh_drops_the_meals_of_days_the_page_no_longer_lists() {
+        let days = fixtures::days(0);
+        let (monday, tuesday) = (days[0].as_str(), days[1].as_str());
+        let menu = |language, days: &[&str]| {
+            (
+                fixtures::menu_url(language, 9, 0),
+                fixtures::menu_page(days, &[meal(monday, "Linsen-Dal"), meal(tuesday, "Chili")]),
+            )
+        };
+        let scraped = fixtures::snapshot(
+            "refresh-scraped",
+            Weeks::Fixed(1),
+            &[
+                (
+                    fixtures::allergen_url("de"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                (
+                    fixtures::allergen_url("en"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                menu("de", &[monday, tuesday]),
+                menu("en", &[monday, tuesday]),
+                (fixtures::menu_url("de", 8, 0), String::new()),
+                (fixtures::menu_url("en", 8, 0), String::new()),
+            ],
+        );
+        let refreshed = fixtures::snapshot(
+            "refresh-refreshed",
+            Weeks::Fixed(1),
+            &[menu("de", &[monday])],
+        );
+        let scraper = fixtures::scraper();
+        let data = scraper
+            .clone()
+            .with_source(PageSource::Replay(scraped))
+            .fetch()
+            .await
+            .unwrap();
+        assert_eq!(names_on(&data, tuesday, "de"), ["Chili"]);
+
+        let scope = RefreshScope::new(vec!["HL_MH".into()], vec![0], vec!["de".into()]);
+        let data = data
+            .refresh(&scraper.with_source(PageSource::Replay(refreshed)), &scope)
+            .await
+            .unwrap();
+        assert_eq!(names_on(&data, monday, "de"), ["Linsen-Dal"]);
+        assert!(names_on(&data, tuesday, "de").is_empty());
+        // The other language still lists the day
+        assert_eq!(names_on(&data, tuesday, "en"), ["Chili"]);
+        assert!(data.get_days().iter().any(|day| day == tuesday));
     }
 
-    pub(crate) fn is_double(&self) -> bool {
-        Self::iter().any(|e| {
-            e.get_ort_code() == self.get_ort_code()
-                && e.get_mensa_code() == self.get_mensa_code()
-                && e != *self
-        })
+    #[tokio::test]
+    async fn replays_refreshes_on_top_of_the_full_scrape() {
+        let days = fixtures::days(0);
+        let (monday, tuesday) = (days[0].as_str(), days[1].as_str());
+        let menu = |language, days: &[&str], dal| {
+            (
+                fixtures::menu_url(language, 9, 0),
+                fixtures::menu_page(days, &[meal(monday, dal), meal(tuesday, "Chili")]),
+            )
+        };
+        let archive = std::env::temp_dir().join(format!("mensa-api-replay-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&archive);
+        let full = fixtures::snapshot_in(
+            &archive.join("20261012T080000Z"),
+            Weeks::Fixed(1),
+            None,
+            &[
+                (
+                    fixtures::allergen_url("de"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                (
+                    fixtures::allergen_url("en"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                menu("de", &[monday, tuesday], "Linsen-Dal"),
+                menu("en", &[monday, tuesday], "Linsen-Dal"),
+                (fixtures::menu_url("de", 8, 0), String::new()),
+                (fixtures::menu_url("en", 8, 0), String::new()),
+            ],
+        );
+        let scope = RefreshScope::new(vec!["HL_MH".into()], vec![0], vec!["de".into()]);
+        let refreshed = fixtures::snapshot_in(
+            &archive.join("20261012T090000.250Z"),
+            Weeks::Fixed(1),
+            Some(&scope),
+            &[menu("de", &[monday], "Linsen-Dal")],
+        );
+        fixtures::snapshot_in(
+            &archive.join("20261012T100000.000Z"),
+            Weeks::Fixed(1),
+            Some(&scope),
+            &[menu("de", &[monday], "Dal")],
+        );
+        let scraper = fixtures::scraper();
+
+        let data = scraper.replay(&full).await.unwrap();
+        assert_eq!(names_on(&data, tuesday, "de"), ["Chili"]);
+
+        let data = scraper.replay(&refreshed).await.unwrap();
+        assert_eq!(names_on(&data, monday, "de"), ["Linsen-Dal"]);
+        assert!(names_on(&data, tuesday, "de").is_empty());
+        assert_eq!(names_on(&data, tuesday, "en"), ["Chili"]);
+        std::fs::remove_dir_all(&archive).unwrap();
     }
-}
 
-impl Into<APILocation> for Location {
-    fn into(self) -> APILocation {
-        match self {
-            Location::LuebeckMusikhochschule => APILocation {
-                code: "HL_MH".to_string(),
-                name: "Musikhochschule".to_string(),
-                city: "Lübeck".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::LuebeckCafeteria => APILocation {
-                code: "HL_CA".to_string(),
-                name: "Cafeteria".to_string(),
-                city: "Lübeck".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::LuebeckMensa => APILocation {
-                code: "HL_ME".to_string(),
-                name: "Mensa".to_string(),
-                city: "Lübeck".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielMensa1 => APILocation {
-                code: "KI_ME1".to_string(),
-                name: "Mensa I".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielCafeteria1 => APILocation {
-                code: "KI_CA1".to_string(),
-                name: "Cafeteria I".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielMensa2 => APILocation {
-                code: "KI_ME2".to_string(),
-                name: "Mensa II".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielCafeteria2 => APILocation {
-                code: "KI_CA2".to_string(),
-                name: "Cafeteria II".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielMensaKesselhaus => APILocation {
-                code: "KI_KESSELHAUS".to_string(),
-                name: "Kesselhaus".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielSchwentine => APILocation {
-                code: "KI_SCHWENTINE".to_string(),
-                name: "Schwentine Mensa".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielDiner => APILocation {
-                code: "KI_DINER".to_string(),
-                name: "American Diner".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::KielDockside => APILocation {
-                code: "KI_DOCKSIDE".to_string(),
-                name: "Mensa „Dockside“".to_string(),
-                city: "Kiel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::HeideMensa => APILocation {
-                code: "HEI_ME1".to_string(),
-                name: "Mensa".to_string(),
-                city: "Heide".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::FlensburgMensa => APILocation {
-                code: "FL_ME1".to_string(),
-                name: "Mensa".to_string(),
-                city: "Flensburg".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::FlensburgCafeteriaA => APILocation {
-                code: "FL_CA1".to_string(),
-                name: "Cafeteria A".to_string(),
-                city: "Flensburg".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::FlensburgCafeteriaB => APILocation {
-                code: "FL_CA2".to_string(),
-                name: "Cafeteria B".to_string(),
-                city: "Flensburg".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::OsterroenfeldMensa => APILocation {
-                code: "RD_ME1".to_string(),
-                name: "Mensa".to_string(),
-                city: "Osterrönfeld".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::WedelCafeteria => APILocation {
-                code: "PI_CA1".to_string(),
-                name: "Cafeteria".to_string(),
-                city: "Wedel".to_string(),
-                available_languages: vec![Language::german(), Language::english()],
-            },
-            Location::LuebeckBitsBytes => APILocation {
-                code: "HL_BB".to_string(),
-                name: "Bits + Bytes".to_string(),
-                city: "Lübeck".to_string(),
-                available_languages: vec![Language::german()],
-            },
-        }
+    #[tokio::test]
+    async fn refresh_replaces_the_warnings_of_the_week_of_the_page() {
+        let monday = fixtures::days(0)[0].clone();
+        let menu = |price| {
+            let meal = FixtureMeal {
+                price,
+                ..meal(&monday, "Linsen-Dal")
+            };
+            (
+                fixtures::menu_url("de", 9, 0),
+                fixtures::menu_page(&[&monday], &[meal]),
+            )
+        };
+        let scraped = fixtures::snapshot(
+            "warnings-scraped",
+            Weeks::Fixed(1),
+            &[
+                (
+                    fixtures::allergen_url("de"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                (
+                    fixtures::allergen_url("en"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                menu("unbekannt"),
+                (fixtures::menu_url("en", 9, 0), String::new()),
+                (fixtures::menu_url("de", 8, 0), String::new()),
+                (fixtures::menu_url("en", 8, 0), String::new()),
+            ],
+        );
+        let refreshed = fixtures::snapshot(
+            "warnings-refreshed",
+            Weeks::Fixed(1),
+            &[menu("3,50 € / 5,00 € / 6,50 €")],
+        );
+        let scraper = fixtures::scraper();
+        let mut data = scraper
+            .clone()
+            .with_source(PageSource::Replay(scraped))
+            .fetch()
+            .await
+            .unwrap();
+        assert_eq!(data.get_warnings().len(), 1);
+        // As if the warning was scraped a week ago, when this week was the next week
+        data.warnings[0].week = 1;
+
+        let scope = RefreshScope::new(vec!["HL_MH".into()], vec![0], vec!["de".into()]);
+        let data = data
+            .refresh(&scraper.with_source(PageSource::Replay(refreshed)), &scope)
+            .await
+            .unwrap();
+        assert!(data.get_warnings().is_empty());
     }
 }
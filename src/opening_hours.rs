@@ -0,0 +1,315 @@
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Weekday};
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The time zone all opening hours are given in
+pub const TIME_ZONE: Tz = chrono_tz::Europe::Berlin;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct OpeningHours {
+    /// The weekly opening hours
+    #[serde(default)]
+    pub(crate) regular: Vec<OpeningTimes>,
+    /// Days on which the regular opening hours do not apply, e.g. holidays
+    #[serde(default)]
+    pub(crate) exceptions: Vec<OpeningException>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct OpeningTimes {
+    /// The days of the week, e.g. "Mon"
+    #[schema(value_type = Vec<String>)]
+    pub(crate) days: Vec<Weekday>,
+    #[serde(flatten)]
+    pub(crate) period: Period,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct OpeningException {
+    pub(crate) date: NaiveDate,
+    /// The opening hours of the day. The location is closed if empty.
+    #[serde(default)]
+    pub(crate) periods: Vec<Period>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct Period {
+    /// The time in the format HH:MM
+    #[serde(with = "hours_and_minutes")]
+    #[schema(value_type = String)]
+    pub(crate) open: NaiveTime,
+    /// The time in the format HH:MM
+    #[serde(with = "hours_and_minutes")]
+    #[schema(value_type = String)]
+    pub(crate) close: NaiveTime,
+}
+
+impl OpeningTimes {
+    pub fn get_days(&self) -> &Vec<Weekday> {
+        &self.days
+    }
+
+    pub fn get_period(&self) -> &Period {
+        &self.period
+    }
+}
+
+impl OpeningException {
+    pub fn get_date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// The opening hours of the day. The location is closed if empty.
+    pub fn get_periods(&self) -> &Vec<Period> {
+        &self.periods
+    }
+}
+
+impl Period {
+    pub fn get_open(&self) -> NaiveTime {
+        self.open
+    }
+
+    pub fn get_close(&self) -> NaiveTime {
+        self.close
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        self.open <= time && time < self.close
+    }
+}
+
+impl OpeningHours {
+    /// The weekly opening hours
+    pub fn get_regular(&self) -> &Vec<OpeningTimes> {
+        &self.regular
+    }
+
+    /// Days on which the regular opening hours do not apply, e.g. holidays
+    pub fn get_exceptions(&self) -> &Vec<OpeningException> {
+        &self.exceptions
+    }
+
+    /// Whether the location is open at the given time. `None` if the opening hours are unknown.
+    pub fn is_open(&self, at: DateTime<Tz>) -> Option<bool> {
+        let date = at.date_naive();
+        let time = at.time();
+
+        if let Some(exception) = self.exceptions.iter().find(|e| e.date == date) {
+            return Some(exception.periods.iter().any(|p| p.contains(time)));
+        }
+        if self.regular.is_empty() {
+            return None;
+        }
+
+        Some(
+            self.regular
+                .iter()
+                .filter(|times| times.days.contains(&date.weekday()))
+                .any(|times| times.period.contains(time)),
+        )
+    }
+}
+
+const TIME: &str = r"(\d{1,2})[:.](\d{2})";
+const TO: &str = r"\s*(?:-|–|bis|to)\s*";
+
+/// Extracts the opening hours from a text like "Mo.-Do.: 11:00 - 14:30 Uhr, Fr 11.00-14.00, Sa geschlossen".
+/// Dates like "24.12.2024: geschlossen" or "23.12.2024 - 27.12.2024: 11:00 - 13:00" become exceptions.
+/// Understands german and english day names. Days without times, e.g. "geschlossen", are closed.
+pub fn parse_opening_hours(text: &str) -> OpeningHours {
+    OpeningHours {
+        regular: parse_regular(text),
+        exceptions: parse_exceptions(text),
+    }
+}
+
+fn parse_regular(text: &str) -> Vec<OpeningTimes> {
+    const DAY: &str = r"(montag|mo|dienstag|di|mittwoch|mi|donnerstag|do|freitag|fr|samstag|sa|sonntag|so|monday|mon|tuesday|tue|wednesday|wed|thursday|thu|friday|fri|saturday|sat|sunday|sun)\b\.?";
+
+    let re = Regex::new(&format!(
+        r"(?i){DAY}(?:{TO}{DAY})?\s*:?\s*{TIME}\s*(?:uhr)?{TO}{TIME}"
+    ))
+    .expect("Opening hours regex failed");
+
+    re.captures_iter(text)
+        .filter_map(|captures| {
+            let first = parse_weekday(&captures[1])?;
+            let last = captures
+                .get(2)
+                .and_then(|day| parse_weekday(day.as_str()))
+                .unwrap_or(first);
+            let time = |hour: usize, minute: usize| {
+                NaiveTime::from_hms_opt(
+                    captures[hour].parse().ok()?,
+                    captures[minute].parse().ok()?,
+                    0,
+                )
+            };
+
+            let mut days = vec![first];
+            let mut day = first;
+            while day != last {
+                day = day.succ();
+                days.push(day);
+            }
+
+            Some(OpeningTimes {
+                days,
+                period: Period {
+                    open: time(3, 4)?,
+                    close: time(5, 6)?,
+                },
+            })
+        })
+        .collect()
+}
+
+fn parse_exceptions(text: &str) -> Vec<OpeningException> {
+    const DATE: &str = r"(\d{1,2})\.(\d{1,2})\.(\d{4})";
+
+    let re = Regex::new(&format!(
+        r"(?i){DATE}(?:{TO}{DATE})?\s*:?\s*(?:(geschlossen|closed)|{TIME}\s*(?:uhr)?{TO}{TIME})"
+    ))
+    .expect("Opening hours exception regex failed");
+
+    re.captures_iter(text)
+        .filter_map(|captures| {
+            let number = |group: usize| captures.get(group)?.as_str().parse::<u32>().ok();
+            let date = |group: usize| {
+                NaiveDate::from_ymd_opt(
+                    number(group + 2)? as i32,
+                    number(group + 1)?,
+                    number(group)?,
+                )
+            };
+            let time =
+                |group: usize| NaiveTime::from_hms_opt(number(group)?, number(group + 1)?, 0);
+
+            let first = date(1)?;
+            let last = captures.get(4).map_or(Some(first), |_| date(4))?;
+            let periods = match captures.get(7) {
+                Some(_) => vec![],
+                None => vec![Period {
+                    open: time(8)?,
+                    close: time(10)?,
+                }],
+            };
+
+            Some(
+                first
+                    .iter_days()
+                    .take_while(|day| *day <= last)
+                    .map(|date| OpeningException {
+                        date,
+                        periods: periods.clone(),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .flatten()
+        .collect()
+}
+
+fn parse_weekday(day: &str) -> Option<Weekday> {
+    match day.to_lowercase().get(..2)? {
+        "mo" => Some(Weekday::Mon),
+        "di" | "tu" => Some(Weekday::Tue),
+        "mi" | "we" => Some(Weekday::Wed),
+        "do" | "th" => Some(Weekday::Thu),
+        "fr" => Some(Weekday::Fri),
+        "sa" => Some(Weekday::Sat),
+        "so" | "su" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// (De)serializes times as "HH:MM"
+pub(crate) mod hours_and_minutes {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%H:%M";
+
+    pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&time.format(FORMAT))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+        let time = String::deserialize(deserializer)?;
+        NaiveTime::parse_from_str(&time, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn parses_day_ranges() {
+        let hours = parse_opening_hours("Mo.-Do.: 11:00 - 14:30 Uhr, Fr 11.00-14.00 Uhr");
+        assert_eq!(hours.regular.len(), 2);
+        assert_eq!(
+            hours.regular[0].days,
+            vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu]
+        );
+        assert_eq!(hours.regular[0].period.open, time(11, 0));
+        assert_eq!(hours.regular[0].period.close, time(14, 30));
+        assert_eq!(hours.regular[1].days, vec![Weekday::Fri]);
+        assert_eq!(hours.regular[1].period.close, time(14, 0));
+    }
+
+    #[test]
+    fn parses_long_day_names() {
+        let hours = parse_opening_hours("Montag bis Freitag 08:00 bis 15:30 Uhr");
+        assert_eq!(hours.regular.len(), 1);
+        assert_eq!(hours.regular[0].days.len(), 5);
+        assert_eq!(hours.regular[0].period.open, time(8, 0));
+    }
+
+    #[test]
+    fn leaves_closed_days_out() {
+        let hours = parse_opening_hours("Mo - Fr 11:30 - 14:00, Sa - So geschlossen");
+        assert_eq!(hours.regular.len(), 1);
+        assert!(!hours.regular[0].days.contains(&Weekday::Sat));
+        assert!(hours.exceptions.is_empty());
+
+        let saturday = TIME_ZONE.with_ymd_and_hms(2024, 6, 8, 12, 0, 0).unwrap();
+        assert_eq!(hours.is_open(saturday), Some(false));
+    }
+
+    #[test]
+    fn parses_exceptions() {
+        let hours = parse_opening_hours(
+            "Mo-Fr 11:00-14:30. 24.12.2024: geschlossen, 27.12.2024 - 30.12.2024: 11:00 - 13:00 Uhr",
+        );
+        assert_eq!(hours.regular.len(), 1);
+        assert_eq!(hours.exceptions.len(), 5);
+        assert_eq!(hours.exceptions[0].date, date(2024, 12, 24));
+        assert!(hours.exceptions[0].periods.is_empty());
+        assert_eq!(hours.exceptions[1].date, date(2024, 12, 27));
+        assert_eq!(hours.exceptions[4].date, date(2024, 12, 30));
+        assert_eq!(hours.exceptions[4].periods[0].close, time(13, 0));
+
+        let christmas_eve = TIME_ZONE.with_ymd_and_hms(2024, 12, 24, 12, 0, 0).unwrap();
+        assert_eq!(hours.is_open(christmas_eve), Some(false));
+    }
+
+    #[test]
+    fn ignores_text_without_opening_hours() {
+        let hours =
+            parse_opening_hours("Die Mensa ist in den Semesterferien eingeschränkt geöffnet.");
+        assert_eq!(hours, OpeningHours::default());
+    }
+}
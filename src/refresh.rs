@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::api_filter::{CommaSeparated, QueryError};
+use crate::cache::Cache;
+use crate::config::Weeks;
+use crate::model::Slice;
+use crate::opening_hours::TIME_ZONE;
+use crate::schedule::Schedule;
+use crate::scrapers::Scraper;
+
+/// What a refresh scrapes again
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshScope {
+    /// The codes of the refreshed locations. All locations, if empty.
+    locations: Vec<String>,
+    /// The refreshed weeks, 0 being the current week. The configured weeks, if empty.
+    weeks: Vec<usize>,
+    /// The codes of the refreshed languages. All languages, if empty.
+    languages: Vec<String>,
+    /// The exact pages to refresh instead of every combination of the locations, weeks and languages.
+    /// These are the union of the pages then.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pages: Vec<ScopedPage>,
+}
+
+/// The menu page of a location in a week and language
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ScopedPage {
+    location: String,
+    week: usize,
+    language: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RefreshQuery {
+    /// Comma separated codes of the locations to refresh. All locations, if not set
+    #[param(value_type = Option<String>)]
+    location: Option<CommaSeparated>,
+    /// Comma separated weeks to refresh, 0 being the current week. The configured weeks, if not set
+    #[param(value_type = Option<String>)]
+    week: Option<CommaSeparated>,
+    /// Comma separated codes of the languages to refresh. All languages, if not set
+    #[param(value_type = Option<String>)]
+    language: Option<CommaSeparated>,
+}
+
+/// The result of a refresh
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RefreshReport {
+    /// The codes of the refreshed locations. All locations, if empty
+    locations: Vec<String>,
+    /// The refreshed weeks. The configured weeks, if empty
+    weeks: Vec<usize>,
+    /// The codes of the refreshed languages. All languages, if empty
+    languages: Vec<String>,
+    started_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    /// The number of menu pages scraped in every refreshed week and language. Failed pages keep their previous meals
+    slices: usize,
+    /// The number of meals served after the refresh
+    meals: usize,
+    /// The number of problems found while parsing the menu pages
+    warnings: usize,
+    /// Whether the request joined a refresh that was already running instead of starting one
+    deduplicated: bool,
+}
+
+type RefreshResult = Result<RefreshReport, String>;
+
+/// The refresh currently running
+struct Running {
+    id: u64,
+    scope: RefreshScope,
+    result: Shared<BoxFuture<'static, RefreshResult>>,
+}
+
+/// Scrapes the data into the cache, either on schedule or on request.
+/// Only one refresh runs at a time. A refresh covered by the running one joins it instead of scraping again.
+pub struct Refresher {
+    scraper: Scraper,
+    state: Arc<Cache>,
+    history_file: Option<String>,
+    running: Mutex<Option<Running>>,
+    next_id: AtomicU64,
+}
+
+impl RefreshScope {
+    pub fn new(locations: Vec<String>, weeks: Vec<usize>, languages: Vec<String>) -> Self {
+        Self {
+            locations,
+            weeks,
+            languages,
+            pages: vec![],
+        }
+    }
+
+    /// The scope of exactly the pages of the given slices. Slices of weeks that are over are left out.
+    fn of_slices<'a>(slices: impl IntoIterator<Item = &'a Slice>) -> Self {
+        let mut scope = Self::default();
+        for slice in slices {
+            let Some(week) = slice.get_week() else {
+                continue;
+            };
+            scope.pages.push(ScopedPage {
+                location: slice.get_location().to_string(),
+                week,
+                language: slice.get_language().to_string(),
+            });
+        }
+        scope.locations = scope
+            .pages
+            .iter()
+            .map(|p| p.location.clone())
+            .unique()
+            .collect();
+        scope.weeks = scope
+            .pages
+            .iter()
+            .map(|p| p.week)
+            .sorted()
+            .dedup()
+            .collect();
+        scope.languages = scope
+            .pages
+            .iter()
+            .map(|p| p.language.clone())
+            .unique()
+            .collect();
+        scope
+    }
+
+    pub fn get_locations(&self) -> &Vec<String> {
+        &self.locations
+    }
+
+    pub fn get_weeks(&self) -> &Vec<usize> {
+        &self.weeks
+    }
+
+    pub fn get_languages(&self) -> &Vec<String> {
+        &self.languages
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.locations.is_empty() && self.weeks.is_empty() && self.languages.is_empty()
+    }
+
+    /// Whether the scope includes the page listing the locations in the week and language.
+    /// The week is `None` for the configured weeks, which a scope of exact pages never includes.
+    pub(crate) fn includes(
+        &self,
+        location_codes: &[&String],
+        week: Option<usize>,
+        language_code: &str,
+    ) -> bool {
+        if !self.pages.is_empty() {
+            return self.pages.iter().any(|page| {
+                location_codes.contains(&&page.location)
+                    && Some(page.week) == week
+                    && page.language == language_code
+            });
+        }
+        (self.locations.is_empty() || location_codes.iter().any(|l| self.locations.contains(l)))
+            && (self.languages.is_empty() || self.languages.iter().any(|l| l == language_code))
+    }
+
+    /// Whether a refresh of this scope also refreshes everything of the other scope.
+    /// Scopes without weeks refresh the configured weeks of the scraper.
+    fn covers(&self, other: &RefreshScope, configured: Weeks) -> bool {
+        if !self.pages.is_empty() {
+            return !other.pages.is_empty() && other.pages.iter().all(|p| self.pages.contains(p));
+        }
+        let covers_week = |week: &usize| match self.weeks.is_empty() {
+            true => configured.includes(*week),
+            false => self.weeks.contains(week),
+        };
+        (self.locations.is_empty() || other.locations.iter().all(|l| self.locations.contains(l)))
+            && (self.weeks.is_empty() || !other.weeks.is_empty())
+            && other.weeks.iter().all(covers_week)
+            && (self.languages.is_empty()
+                || other.languages.iter().all(|l| self.languages.contains(l)))
+    }
+}
+
+impl RefreshQuery {
+    /// Checks the locations, weeks and languages against the ones the scraper knows
+    pub fn scope(&self, scraper: &Scraper) -> Result<RefreshScope, QueryError> {
+        let locations = values(&self.location);
+        if let Some(code) = locations
+            .iter()
+            .find(|code| scraper.get_locations().get(code).is_none())
+        {
+            return Err(QueryError::new(format!("Unknown location '{code}'")));
+        }
+
+        let weeks = values(&self.week)
+            .iter()
+            .map(|week| match week.parse::<usize>() {
+                Ok(week) if week < Weeks::MAX_AUTO => Ok(week),
+                _ => Err(QueryError::new(format!(
+                    "Invalid week '{week}'. Expected a number from 0 to {}",
+                    Weeks::MAX_AUTO - 1
+                ))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let languages = values(&self.language);
+        if let Some(code) = languages
+            .iter()
+            .find(|code| scraper.get_languages().get(code).is_none())
+        {
+            return Err(QueryError::new(format!("Unknown language '{code}'")));
+        }
+
+        Ok(RefreshScope::new(locations, weeks, languages))
+    }
+}
+
+fn slice_key(slice: &Slice) -> (String, NaiveDate, String) {
+    (
+        slice.get_location().to_string(),
+        slice.get_week_start(),
+        slice.get_language().to_string(),
+    )
+}
+
+fn values(list: &Option<CommaSeparated>) -> Vec<String> {
+    list.as_deref().unwrap_or_default().to_vec()
+}
+
+impl Refresher {
+    pub fn new(scraper: Scraper, state: Arc<Cache>, history_file: Option<String>) -> Self {
+        Self {
+            scraper,
+            state,
+            history_file,
+            running: Mutex::new(None),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get_scraper(&self) -> &Scraper {
+        &self.scraper
+    }
+
+    /// Refreshes the data on schedule. Every slice is due at the next refresh of the schedule after its last update.
+    /// The first refresh of a day scrapes everything, so the allergens, the opening hours and the weeks follow the website.
+    pub async fn run_schedule(self: Arc<Self>, schedule: Schedule) {
+        // When the slices were last tried to refresh, so failed pages wait for the next refresh of the schedule
+        let mut attempts: HashMap<(String, NaiveDate, String), DateTime<Utc>> = HashMap::new();
+        let mut full_attempt = None;
+        loop {
+            let now = Utc::now();
+            let data = self.state.get_data().ok();
+
+            let full_due = match &data {
+                Some(data) if full_attempt.is_some_and(|at| at > data.get_last_updated()) => {
+                    full_attempt.map(|at| schedule.next_refresh(at))
+                }
+                Some(data) => Some(schedule.next_day(data.get_last_updated())),
+                None => full_attempt.map(|at| schedule.next_refresh(at)),
+            }
+            .unwrap_or(now);
+            let slices: Vec<(&Slice, DateTime<Utc>)> = data
+                .iter()
+                .flat_map(|data| data.get_slices())
+                .filter_map(|slice| Some((slice, slice.get_week()?)))
+                .map(|(slice, week)| {
+                    let attempt = attempts.get(&slice_key(slice)).copied();
+                    let updated = attempt.map_or(slice.get_last_updated(), |attempt| {
+                        attempt.max(slice.get_last_updated())
+                    });
+                    let due = schedule.next_refresh_of(slice.get_locations(), week, updated);
+                    (slice, due)
+                })
+                .collect();
+
+            let scope = if full_due <= now {
+                full_attempt = Some(now);
+                attempts.clear();
+                Some(RefreshScope::default())
+            } else {
+                let due: Vec<&Slice> = slices
+                    .iter()
+                    .filter(|(_, due)| *due <= now)
+                    .map(|(slice, _)| *slice)
+                    .collect();
+                for slice in &due {
+                    attempts.insert(slice_key(slice), now);
+                }
+                (!due.is_empty()).then(|| RefreshScope::of_slices(due))
+            };
+
+            match scope {
+                Some(scope) => {
+                    if let Err(e) = self.refresh(scope).await {
+                        eprint!("{e}");
+                    }
+                }
+                None => {
+                    let next = slices
+                        .iter()
+                        .map(|(_, due)| *due)
+                        .chain([full_due])
+                        .min()
+                        .unwrap_or(full_due);
+                    println!("Next refresh at {}", next.with_timezone(&TIME_ZONE));
+                    tokio::time::sleep((next - now).to_std().unwrap_or_default()).await;
+                }
+            }
+        }
+    }
+
+    /// Refreshes the scope and waits for the result.
+    /// Joins the running refresh, if it covers the scope. Otherwise the refresh starts after the running one.
+    /// The refresh finishes even if the caller stops waiting.
+    pub async fn refresh(self: &Arc<Self>, scope: RefreshScope) -> anyhow::Result<RefreshReport> {
+        let (result, deduplicated) = {
+            let mut running = self
+                .running
+                .lock()
+                .map_err(|_| anyhow!("Refresh lock poisoned"))?;
+            match running.as_ref() {
+                Some(current) if current.scope.covers(&scope, self.scraper.get_weeks()) => {
+                    (current.result.clone(), true)
+                }
+                current => {
+                    let previous = current.map(|current| current.result.clone());
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    let task = tokio::spawn({
+                        let refresher = self.clone();
+                        let scope = scope.clone();
+                        async move {
+                            if let Some(previous) = previous {
+                                let _ = previous.await;
+                            }
+                            let result = refresher.run(&scope).await.map_err(|e| e.to_string());
+                            if let Ok(mut running) = refresher.running.lock() {
+                                if running.as_ref().is_some_and(|running| running.id == id) {
+                                    *running = None;
+                                }
+                            }
+                            result
+                        }
+                    });
+                    let result = async move {
+                        task.await
+                            .unwrap_or_else(|e| Err(format!("Refresh task failed: {e}")))
+                    }
+                    .boxed()
+                    .shared();
+                    *running = Some(Running {
+                        id,
+                        scope: scope.clone(),
+                        result: result.clone(),
+                    });
+                    (result, false)
+                }
+            }
+        };
+
+        let mut report = result.await.map_err(|e| anyhow!(e))?;
+        report.deduplicated = deduplicated;
+        Ok(report)
+    }
+
+    async fn run(&self, scope: &RefreshScope) -> anyhow::Result<RefreshReport> {
+        println!("{} Starting to load data...", chrono::Local::now());
+        let started_at = Utc::now();
+        let current = match scope.is_full() {
+            true => None,
+            false => self
+                .state
+                .get_data()
+                .ok()
+                .map(|cached| cached.get_data().clone()),
+        };
+        // Without data to refresh a part of, everything is scraped
+        let (data, scope) = match current {
+            Some(current) => (current.refresh(&self.scraper, scope).await?, scope.clone()),
+            None => (
+                Cache::fetch_data(&self.scraper).await?,
+                RefreshScope::default(),
+            ),
+        };
+        let report = RefreshReport {
+            locations: scope.locations,
+            weeks: scope.weeks,
+            languages: scope.languages,
+            slices: data
+                .get_slices()
+                .iter()
+                .filter(|slice| slice.get_last_updated() >= started_at)
+                .count(),
+            started_at,
+            finished_at: Utc::now(),
+            meals: data.get_meals().len(),
+            warnings: data.get_warnings().len(),
+            deduplicated: false,
+        };
+
+        self.state.set_data(data)?;
+        println!("{} Cache refreshed...", chrono::Local::now());
+        if let Some(history_file) = self.history_file.clone() {
+            let history = self.state.get_history();
+            let saved = tokio::task::spawn_blocking(move || history.save(history_file)).await;
+            if let Err(e) = saved.map_err(anyhow::Error::from).and_then(|saved| saved) {
+                eprint!("{e}");
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::week_start;
+
+    fn slice(location: &str, week: usize, language: &str) -> Slice {
+        Slice {
+            location: location.to_string(),
+            locations: vec![location.to_string()],
+            week_start: week_start(week),
+            language: language.to_string(),
+            days: vec![],
+            last_updated: Utc::now(),
+        }
+    }
+
+    fn strings(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(|code| code.to_string()).collect()
+    }
+
+    #[test]
+    fn includes_only_the_pages_of_the_slices() {
+        let scope = RefreshScope::of_slices(&[slice("HL_MH", 0, "de"), slice("HL_ME", 1, "en")]);
+        let mh = "HL_MH".to_string();
+        let me = "HL_ME".to_string();
+        assert!(scope.includes(&[&mh], Some(0), "de"));
+        assert!(scope.includes(&[&me], Some(1), "en"));
+        // Every combination of the locations, weeks and languages would refresh these as well
+        assert!(!scope.includes(&[&mh], Some(1), "de"));
+        assert!(!scope.includes(&[&mh], Some(0), "en"));
+        assert!(!scope.includes(&[&me], Some(0), "en"));
+        assert!(!scope.includes(&[&mh], None, "de"));
+    }
+
+    #[test]
+    fn leaves_out_the_weeks_that_are_over() {
+        let mut over = slice("HL_MH", 0, "de");
+        over.week_start = week_start(0) - chrono::Days::new(7);
+        let scope = RefreshScope::of_slices(&[over, slice("HL_ME", 0, "de")]);
+        assert_eq!(scope.get_locations(), &strings(&["HL_ME"]));
+        assert_eq!(scope.get_weeks(), &vec![0]);
+    }
+
+    #[test]
+    fn covers_the_contained_scopes() {
+        let pages = RefreshScope::of_slices(&[slice("HL_MH", 0, "de"), slice("HL_ME", 1, "en")]);
+        let page = RefreshScope::of_slices(&[slice("HL_ME", 1, "en")]);
+        let location = RefreshScope::new(strings(&["HL_ME"]), vec![], vec![]);
+        let configured = Weeks::Fixed(2);
+        assert!(pages.covers(&page, configured));
+        assert!(!page.covers(&pages, configured));
+        assert!(!pages.covers(&location, configured));
+        assert!(location.covers(&page, configured));
+        assert!(RefreshScope::default().covers(&pages, configured));
+
+        // A full refresh only scrapes the configured weeks
+        let later = RefreshScope::new(vec![], vec![3], vec![]);
+        assert!(!RefreshScope::default().covers(&later, configured));
+        assert!(!location.covers(
+            &RefreshScope::new(strings(&["HL_ME"]), vec![3], vec![]),
+            configured
+        ));
+        assert!(
+            RefreshScope::default().covers(&RefreshScope::new(vec![], vec![1], vec![]), configured)
+        );
+        assert!(RefreshScope::new(vec![], vec![3], vec![]).covers(&later, configured));
+        // Nor does a refresh of some weeks cover the configured weeks
+        assert!(!later.covers(&RefreshScope::default(), configured));
+    }
+}
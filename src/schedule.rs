@@ -0,0 +1,376 @@
+use anyhow::anyhow;
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Deserializer};
+
+use crate::opening_hours::{hours_and_minutes, TIME_ZONE};
+
+/// When the data is scraped again, in the time zone of the opening hours
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    rules: Vec<ScheduleRule>,
+    /// Skips the refreshes on the public holidays of Schleswig-Holstein
+    skip_holidays: bool,
+    /// The interval if no rule matches
+    default_interval: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScheduleFile {
+    #[serde(default)]
+    skip_holidays: bool,
+    #[serde(default)]
+    rule: Vec<ScheduleRule>,
+}
+
+/// A time window with its own refresh interval
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleRule {
+    /// The codes of the locations whose menu pages the rule applies to. All locations, if empty
+    #[serde(default)]
+    locations: Vec<String>,
+    /// The weeks the rule applies to, 0 being the current week. All weeks, if empty
+    #[serde(default)]
+    weeks: Vec<usize>,
+    /// The days of the week, e.g. "mon". Every day, if empty
+    #[serde(default)]
+    days: Vec<Weekday>,
+    /// The start of the window in the format HH:MM. Default: 00:00
+    #[serde(default, deserialize_with = "hours_and_minutes::deserialize")]
+    from: NaiveTime,
+    /// The end of the window in the format HH:MM, excluded. The end of the day, if not set
+    #[serde(default, deserialize_with = "optional_hours_and_minutes")]
+    to: Option<NaiveTime>,
+    /// The seconds between two refreshes
+    interval: u32,
+}
+
+impl Schedule {
+    /// Loads the schedule from the given TOML file or refreshes every `ttl` seconds
+    pub fn load(path: Option<&str>, ttl: u32) -> anyhow::Result<Self> {
+        let default_interval = Duration::seconds(ttl as i64);
+        let Some(path) = path else {
+            return Ok(Self {
+                rules: vec![],
+                skip_holidays: false,
+                default_interval,
+            });
+        };
+
+        Self::from_toml(&std::fs::read_to_string(path)?, ttl)
+    }
+
+    /// Parses the schedule. Times no rule matches are refreshed every `ttl` seconds.
+    pub fn from_toml(toml: &str, ttl: u32) -> anyhow::Result<Self> {
+        let file: ScheduleFile = toml::from_str(toml)?;
+        for rule in &file.rule {
+            if rule.interval == 0 {
+                return Err(anyhow!("The interval of a schedule rule must not be 0"));
+            }
+            if rule.to.is_some_and(|to| to <= rule.from) {
+                return Err(anyhow!(
+                    "The schedule rule from {} must end after it starts",
+                    rule.from.format("%H:%M")
+                ));
+            }
+        }
+
+        Ok(Self {
+            rules: file.rule,
+            skip_holidays: file.skip_holidays,
+            default_interval: Duration::seconds(ttl as i64),
+        })
+    }
+
+    /// The time of the refresh after the one at the given time.
+    /// Only the rules for all locations and weeks apply.
+    pub fn next_refresh(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        self.next_refresh_where(after, |rule| {
+            rule.locations.is_empty() && rule.weeks.is_empty()
+        })
+    }
+
+    /// The time of the refresh of a menu page after the one at the given time.
+    /// The rules for the locations listed on the page or for its week apply besides the rules for all pages.
+    pub fn next_refresh_of(
+        &self,
+        location_codes: &[String],
+        week: usize,
+        after: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        self.next_refresh_where(after, |rule| {
+            (rule.locations.is_empty() || rule.locations.iter().any(|l| location_codes.contains(l)))
+                && (rule.weeks.is_empty() || rule.weeks.contains(&week))
+        })
+    }
+
+    fn next_refresh_where(
+        &self,
+        after: DateTime<Utc>,
+        applies: impl Fn(&ScheduleRule) -> bool,
+    ) -> DateTime<Utc> {
+        let rules: Vec<&ScheduleRule> = self.rules.iter().filter(|rule| applies(rule)).collect();
+        let local = after.with_timezone(&TIME_ZONE);
+        let mut next = local + self.interval_at(&rules, local);
+        // Refreshes when a window starts, so a shorter interval applies on time
+        if let Some(start) = next_window_start(&rules, local) {
+            next = next.min(start);
+        }
+        while self.skip_holidays && is_public_holiday(next.date_naive()) {
+            next = start_of_day(next.date_naive() + Days::new(1));
+        }
+        next.with_timezone(&Utc)
+    }
+
+    /// The start of the day after the given time. Public holidays are skipped, if configured.
+    pub fn next_day(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        let mut next = start_of_day(after.with_timezone(&TIME_ZONE).date_naive() + Days::new(1));
+        while self.skip_holidays && is_public_holiday(next.date_naive()) {
+            next = start_of_day(next.date_naive() + Days::new(1));
+        }
+        next.with_timezone(&Utc)
+    }
+
+    fn interval_at(&self, rules: &[&ScheduleRule], at: DateTime<Tz>) -> Duration {
+        rules
+            .iter()
+            .find(|rule| rule.applies_on(at.date_naive()) && rule.contains(at.time()))
+            .map(|rule| Duration::seconds(rule.interval as i64))
+            .unwrap_or(self.default_interval)
+    }
+}
+
+fn next_window_start(rules: &[&ScheduleRule], after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let today = after.date_naive();
+    [today, today + Days::new(1)]
+        .into_iter()
+        .flat_map(|date| {
+            rules
+                .iter()
+                .filter(move |rule| rule.applies_on(date))
+                .filter_map(move |rule| {
+                    TIME_ZONE
+                        .from_local_datetime(&date.and_time(rule.from))
+                        .earliest()
+                })
+        })
+        .filter(|start| *start > after)
+        .min()
+}
+
+impl ScheduleRule {
+    fn applies_on(&self, date: NaiveDate) -> bool {
+        self.days.is_empty() || self.days.contains(&date.weekday())
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        self.from <= time && self.to.map(|to| time < to).unwrap_or(true)
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> DateTime<Tz> {
+    TIME_ZONE
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+        .unwrap_or_else(|| {
+            date.and_time(NaiveTime::MIN)
+                .and_utc()
+                .with_timezone(&TIME_ZONE)
+        })
+}
+
+/// Whether the date is a public holiday in Schleswig-Holstein
+pub fn is_public_holiday(date: NaiveDate) -> bool {
+    let fixed = [(1, 1), (5, 1), (10, 3), (10, 31), (12, 25), (12, 26)];
+    if fixed.contains(&(date.month(), date.day())) {
+        return true;
+    }
+
+    let Some(easter) = easter_sunday(date.year()) else {
+        return false;
+    };
+    // Good Friday, Easter Monday, Ascension Day and Whit Monday
+    [-2, 1, 39, 50]
+        .into_iter()
+        .any(|offset| easter + Duration::days(offset) == date)
+}
+
+/// The date of easter sunday in the gregorian calendar, computed with the anonymous gregorian algorithm
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+fn optional_hours_and_minutes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    hours_and_minutes::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The schedule shipped with the API
+    fn schedule() -> Schedule {
+        Schedule::from_toml(include_str!("../schedule.toml"), 2700).unwrap()
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        TIME_ZONE
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .single()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn refreshes_when_a_shorter_window_starts() {
+        // Tuesday, the hourly refresh would be at 10:30
+        assert_eq!(
+            schedule().next_refresh(local(2024, 10, 15, 9, 30)),
+            local(2024, 10, 15, 10, 0)
+        );
+    }
+
+    #[test]
+    fn keeps_the_interval_of_the_window_past_its_end() {
+        assert_eq!(
+            schedule().next_refresh(local(2024, 10, 15, 13, 55)),
+            local(2024, 10, 15, 14, 5)
+        );
+    }
+
+    #[test]
+    fn refreshes_hourly_on_weekends() {
+        // Friday evening
+        assert_eq!(
+            schedule().next_refresh(local(2024, 10, 18, 18, 0)),
+            local(2024, 10, 18, 19, 0)
+        );
+        // Saturday, when the window of the weekdays would start
+        assert_eq!(
+            schedule().next_refresh(local(2024, 10, 19, 10, 0)),
+            local(2024, 10, 19, 11, 0)
+        );
+    }
+
+    #[test]
+    fn skips_public_holidays() {
+        // Wednesday before Ascension Day
+        assert_eq!(
+            schedule().next_refresh(local(2024, 5, 8, 23, 30)),
+            local(2024, 5, 10, 0, 0)
+        );
+        assert_eq!(
+            schedule().next_day(local(2024, 12, 24, 12, 0)),
+            local(2024, 12, 27, 0, 0)
+        );
+    }
+
+    #[test]
+    fn counts_the_interval_in_absolute_time_on_dst_switches() {
+        // 02:00 does not exist on 2024-03-31, so an hour after 01:30 is 03:30
+        assert_eq!(
+            schedule().next_refresh(local(2024, 3, 31, 1, 30)),
+            local(2024, 3, 31, 3, 30)
+        );
+        // 02:00 to 03:00 happens twice on 2024-10-27
+        let before_switch = TIME_ZONE
+            .with_ymd_and_hms(2024, 10, 27, 2, 30, 0)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            schedule().next_refresh(before_switch),
+            before_switch + Duration::hours(1)
+        );
+    }
+
+    #[test]
+    fn applies_the_rules_of_a_location_and_week() {
+        let schedule = Schedule::from_toml(
+            r#"
+            [[rule]]
+            locations = ["HL_ME"]
+            from = "11:00"
+            to = "14:00"
+            interval = 300
+
+            [[rule]]
+            weeks = [1]
+            interval = 21600
+
+            [[rule]]
+            interval = 3600
+            "#,
+            2700,
+        )
+        .unwrap();
+
+        let noon = local(2024, 10, 15, 12, 0);
+        let canteen = ["HL_CA".to_string(), "HL_ME".to_string()];
+        let academy = ["HL_MH".to_string()];
+        assert_eq!(
+            schedule.next_refresh_of(&canteen, 0, noon),
+            local(2024, 10, 15, 12, 5)
+        );
+        assert_eq!(
+            schedule.next_refresh_of(&academy, 0, noon),
+            local(2024, 10, 15, 13, 0)
+        );
+        assert_eq!(
+            schedule.next_refresh_of(&academy, 1, noon),
+            local(2024, 10, 15, 18, 0)
+        );
+        // The window of the location starts before the next refresh of other pages
+        assert_eq!(
+            schedule.next_refresh_of(&canteen, 0, local(2024, 10, 15, 10, 30)),
+            local(2024, 10, 15, 11, 0)
+        );
+        assert_eq!(schedule.next_refresh(noon), local(2024, 10, 15, 13, 0));
+    }
+
+    #[test]
+    fn computes_easter_sunday() {
+        assert_eq!(easter_sunday(2024), Some(date(2024, 3, 31)));
+        assert_eq!(easter_sunday(2025), Some(date(2025, 4, 20)));
+        assert_eq!(easter_sunday(2038), Some(date(2038, 4, 25)));
+    }
+
+    #[test]
+    fn knows_the_public_holidays() {
+        // Good Friday, Easter Monday, Ascension Day and Whit Monday 2024
+        for holiday in [
+            date(2024, 3, 29),
+            date(2024, 4, 1),
+            date(2024, 5, 9),
+            date(2024, 5, 20),
+        ] {
+            assert!(is_public_holiday(holiday), "{holiday}");
+        }
+        for holiday in [date(2024, 1, 1), date(2024, 10, 3), date(2024, 10, 31)] {
+            assert!(is_public_holiday(holiday), "{holiday}");
+        }
+        for workday in [date(2024, 3, 28), date(2024, 5, 10), date(2024, 12, 24)] {
+            assert!(!is_public_holiday(workday), "{workday}");
+        }
+    }
+}
@@ -1,4 +1,7 @@
+use std::sync::Arc;
+
 use anyhow::anyhow;
+use chrono::Utc;
 use htmlentity::entity::decode;
 use htmlentity::entity::ICodedDataTrait;
 use itertools::Itertools;
@@ -6,50 +9,311 @@ use regex::Regex;
 use scraper::Html;
 use scraper::Selector;
 
-use crate::model::{Allergen, Language, Location, Meal};
+use crate::archive::{MenuPage, PageCache, PageFetcher, PageSource, Snapshot};
+use crate::config::Weeks;
+use crate::diagnostics::{PageWarnings, ScrapeWarning, WarningKind};
+use crate::languages::{LanguageConfig, Languages};
+use crate::locations::{LocationConfig, Locations, OpeningHoursSource};
+use crate::model::{week_start, APILocation, Allergen, Data, Language, Meal, Prices, Slice};
+use crate::opening_hours::{parse_opening_hours, OpeningHours};
+use crate::refresh::RefreshScope;
 use futures::future::join_all;
-use strum::IntoEnumIterator;
 
-pub async fn scrape_meals(allergens: &Vec<Allergen>) -> anyhow::Result<Vec<Meal>> {
-    // 0,1
-    let weeks = 0..2;
-    let langs = vec![Language::german(), Language::english()];
+/// The meals of one or more menu pages
+#[derive(Debug, Clone)]
+pub struct Menu {
+    /// The days listed on the menu pages, including days without any meals
+    pub days: Vec<String>,
+    pub meals: Vec<Meal>,
+    /// The problems found while parsing the menu pages
+    pub warnings: Vec<ScrapeWarning>,
+    /// One slice for every scraped menu page
+    pub slices: Vec<Slice>,
+}
+
+impl FromIterator<Menu> for Menu {
+    fn from_iter<T: IntoIterator<Item = Menu>>(iter: T) -> Self {
+        let menus: Vec<Menu> = iter.into_iter().collect();
+        Menu {
+            days: menus
+                .iter()
+                .flat_map(|m| m.days.iter().cloned())
+                .sorted()
+                .dedup()
+                .collect(),
+            warnings: menus
+                .iter()
+                .flat_map(|m| m.warnings.iter().cloned())
+                .collect(),
+            slices: menus
+                .iter()
+                .flat_map(|m| m.slices.iter().cloned())
+                .collect(),
+            meals: menus.into_iter().flat_map(|m| m.meals).collect(),
+        }
+    }
+}
+
+/// Scrapes the menus of the studentenwerk.sh website.
+/// Holds everything that determines what is scraped, so batch jobs can fetch the data without the web server.
+#[derive(Debug, Clone)]
+pub struct Scraper {
+    locations: Locations,
+    languages: Languages,
+    weeks: Weeks,
+    source: PageSource,
+    /// Shared by the clones, so a copy with other settings still knows the fetched pages
+    page_cache: Arc<PageCache>,
+}
+
+impl Default for Scraper {
+    /// Scrapes the locations and languages shipped with the API
+    fn default() -> Self {
+        Self::new(
+            Locations::load(None).expect("The shipped locations are invalid"),
+            Languages::load(None).expect("The shipped languages are invalid"),
+        )
+    }
+}
+
+impl Scraper {
+    pub fn new(locations: Locations, languages: Languages) -> Self {
+        Self {
+            locations,
+            languages,
+            weeks: Weeks::default(),
+            source: PageSource::default(),
+            page_cache: Arc::default(),
+        }
+    }
+
+    /// Scrapes only the given locations, e.g. a subset of the configured locations
+    pub fn with_locations(mut self, locations: Locations) -> Self {
+        self.locations = locations;
+        self
+    }
+
+    pub fn with_weeks(mut self, weeks: Weeks) -> Self {
+        self.weeks = weeks;
+        self
+    }
+
+    /// Archives the scraped pages or replays an archived snapshot.
+    /// A replay scrapes the weeks of the snapshot.
+    pub fn with_source(mut self, source: PageSource) -> Self {
+        if let PageSource::Replay(snapshot) = &source {
+            self.weeks = snapshot.get_weeks();
+        }
+        self.source = source;
+        self
+    }
+
+    pub fn get_locations(&self) -> &Locations {
+        &self.locations
+    }
+
+    pub fn get_languages(&self) -> &Languages {
+        &self.languages
+    }
+
+    pub fn get_weeks(&self) -> Weeks {
+        self.weeks
+    }
+
+    pub fn get_source(&self) -> &PageSource {
+        &self.source
+    }
+
+    /// The source of the scrapes that are not archived as a snapshot.
+    /// Only scrapes of the data, full or partial, write the index a snapshot is replayed from.
+    pub(crate) fn get_unarchived_source(&self) -> &PageSource {
+        match &self.source {
+            PageSource::Archived(_) => &PageSource::Live,
+            source => source,
+        }
+    }
+
+    pub(crate) fn get_page_cache(&self) -> &PageCache {
+        &self.page_cache
+    }
 
-    let futures = weeks
-        .cartesian_product(Location::iter().unique_by(|l| (l.get_mensa_code(), l.get_ort_code())))
-        .cartesian_product(langs)
-        .map(|((week, location), language)| {
-            scrape_meals_of_week(location, language, week, allergens)
-        });
+    /// Scrapes the allergens and additives in every language.
+    /// Not archived, only [`Scraper::fetch`] and the refreshes of its data archive their pages as snapshots.
+    pub async fn scrape_allergens(&self) -> anyhow::Result<Vec<Allergen>> {
+        let pages = PageFetcher::new(self.get_unarchived_source(), &self.page_cache);
+        scrape_allergens(&self.languages, &pages).await
+    }
 
-    let vecs_of_meals = join_all(futures)
+    /// Scrapes the meals of every location in every language.
+    /// The allergens are looked up in the given allergens.
+    /// Not archived, only [`Scraper::fetch`] and the refreshes of its data archive their pages as snapshots.
+    pub async fn scrape_meals(&self, allergens: &[Allergen]) -> anyhow::Result<Menu> {
+        let pages = PageFetcher::new(self.get_unarchived_source(), &self.page_cache);
+        self.scrape_meals_from(&pages, allergens).await
+    }
+
+    pub(crate) async fn scrape_allergens_from(
+        &self,
+        pages: &PageFetcher<'_>,
+    ) -> anyhow::Result<Vec<Allergen>> {
+        scrape_allergens(&self.languages, pages).await
+    }
+
+    pub(crate) async fn scrape_meals_from(
+        &self,
+        pages: &PageFetcher<'_>,
+        allergens: &[Allergen],
+    ) -> anyhow::Result<Menu> {
+        scrape_meals(
+            allergens,
+            &self.locations,
+            &self.languages,
+            self.weeks,
+            pages,
+        )
         .await
-        .into_iter()
-        .collect::<anyhow::Result<Vec<_>>>()?;
+    }
 
-    Ok(vecs_of_meals.into_iter().flatten().collect())
+    /// Scrapes the meals of the pages in the scope.
+    /// Pages that fail are skipped, so their slices keep their previous meals.
+    pub(crate) async fn scrape_meals_of(
+        &self,
+        pages: &PageFetcher<'_>,
+        allergens: &[Allergen],
+        scope: &RefreshScope,
+    ) -> anyhow::Result<Menu> {
+        let weeks = match scope.get_weeks().is_empty() {
+            true => vec![self.weeks],
+            false => scope
+                .get_weeks()
+                .iter()
+                .map(|&week| Weeks::Single(week))
+                .collect(),
+        };
+        let futures = self
+            .locations
+            .pages()
+            .cartesian_product(self.languages.iter())
+            .cartesian_product(weeks)
+            .filter(|((location, language), weeks)| {
+                let codes: Vec<&String> = self
+                    .locations
+                    .sharing_page(location)
+                    .map(|l| &l.code)
+                    .collect();
+                let week = match weeks {
+                    Weeks::Single(week) => Some(*week),
+                    _ => None,
+                };
+                scope.includes(&codes, week, &language.code)
+            })
+            .map(|((location, language), weeks)| {
+                scrape_meals_of_weeks(location, &self.locations, language, weeks, allergens, pages)
+            });
+
+        let results = join_all(futures).await;
+        let scraped = results.len();
+        let menus: Vec<Menu> = results
+            .into_iter()
+            .filter_map(|menu| {
+                menu.map_err(|e| eprintln!("Failed to refresh a page: {e}"))
+                    .ok()
+            })
+            .collect();
+        if scraped > 0 && menus.is_empty() {
+            return Err(anyhow!("Failed to refresh all {scraped} pages"));
+        }
+        Ok(menus.into_iter().collect())
+    }
+
+    /// Scrapes all data, as served by the API
+    pub async fn fetch(&self) -> anyhow::Result<Data> {
+        Data::fetch(self).await
+    }
+
+    /// Scrapes the data of an archived snapshot again, see [`Snapshot::replay_chain`]
+    pub async fn replay(&self, snapshot: &Snapshot) -> anyhow::Result<Data> {
+        Data::replay(self, snapshot).await
+    }
+}
+
+async fn scrape_meals(
+    allergens: &[Allergen],
+    locations: &Locations,
+    languages: &Languages,
+    weeks: Weeks,
+    pages: &PageFetcher<'_>,
+) -> anyhow::Result<Menu> {
+    let futures =
+        locations
+            .pages()
+            .cartesian_product(languages.iter())
+            .map(|(location, language)| {
+                scrape_meals_of_weeks(location, locations, language, weeks, allergens, pages)
+            });
+
+    join_all(futures).await.into_iter().collect()
+}
+
+async fn scrape_meals_of_weeks(
+    location: &LocationConfig,
+    locations: &Locations,
+    language: &LanguageConfig,
+    weeks: Weeks,
+    allergens: &[Allergen],
+    pages: &PageFetcher<'_>,
+) -> anyhow::Result<Menu> {
+    match weeks {
+        Weeks::Fixed(weeks) => join_all((0..weeks).map(|week| {
+            scrape_meals_of_week(location, locations, language, week, allergens, pages)
+        }))
+        .await
+        .into_iter()
+        .collect(),
+        Weeks::Auto => {
+            let mut menus = vec![];
+            for week in 0..Weeks::MAX_AUTO {
+                let menu =
+                    scrape_meals_of_week(location, locations, language, week, allergens, pages)
+                        .await?;
+                // The website lists no days for weeks without a published menu
+                if menu.days.is_empty() {
+                    break;
+                }
+                menus.push(menu);
+            }
+            Ok(menus.into_iter().collect())
+        }
+        Weeks::Single(week) => {
+            scrape_meals_of_week(location, locations, language, week, allergens, pages).await
+        }
+    }
 }
 
 async fn scrape_meals_of_week(
-    location: Location,
-    language: Language,
+    location: &LocationConfig,
+    locations: &Locations,
+    language_config: &LanguageConfig,
     week: usize,
-    allergens: &Vec<Allergen>,
-) -> anyhow::Result<Vec<Meal>> {
+    allergens: &[Allergen],
+    pages: &PageFetcher<'_>,
+) -> anyhow::Result<Menu> {
+    let language = language_config.language();
     let url = format!(
         "https://studentenwerk.sh/{}/{}?ort={}&mensa={}&nw={}#mensaplan",
-        language.code,
-        if language.code == "en" {
-            "food-overview"
-        } else {
-            "essen-uebersicht"
-        },
-        location.get_ort_code(),
-        location.get_mensa_code(),
-        week
+        language.code, language_config.menu_slug, location.ort, location.mensa, week
     );
 
-    let html = reqwest::get(url).await?.text().await?;
+    let html = match pages.get_menu_page(&url, allergens).await? {
+        MenuPage::Unchanged(mut menu) => {
+            for slice in &mut menu.slices {
+                slice.week_start = week_start(week);
+                slice.last_updated = Utc::now();
+            }
+            return Ok(menu);
+        }
+        MenuPage::Changed(html) => html,
+    };
 
     let document = Html::parse_document(&html);
 
@@ -61,41 +325,67 @@ async fn scrape_meals_of_week(
         Selector::parse(".menu_art").expect("Menu location selector failed");
     let day_element_selector =
         Selector::parse(".tag_headline[data-day]").expect("Day element selector failed");
+    let undated_day_selector =
+        Selector::parse(".tag_headline:not([data-day])").expect("Undated day selector failed");
+
+    let mut warnings = PageWarnings::new(&location.code, week, &language.code);
+
+    for day_container in document.select(&undated_day_selector) {
+        if day_container.select(&meal_info_selector).next().is_some() {
+            warnings.push(
+                WarningKind::MissingDate,
+                "Day without data-day attribute",
+                Some(day_container.html()),
+            );
+        }
+    }
 
-    document
+    let days: Vec<String> = document
         .select(&day_element_selector)
-        .map(|day_container| {
-            let date_str = day_container.attr("data-day");
-            (date_str, day_container)
-        })
-        .flat_map(|(date_str, day_container)| {
-            let date_str = date_str.clone();
-            day_container
-                .select(&meal_info_selector)
-                .map(move |meal_info| (date_str, meal_info))
-        })
-        .map(|(date_str, meal_info)| -> anyhow::Result<Meal> {
-            let name = meal_info
-                .select(&name_selector)
-                .next()
-                .ok_or(anyhow!("Failed to select meal name element"))
-                .and_then(|name_el| {
-                    let re = Regex::new(
-                        r#"</?\w+((\s+\w+(\s*=\s*(?:".*?"|'.*?'|[^'">\s]+))?)+\s*|\s*)/?>"#,
-                    )
-                    .expect("Name Regex failed");
-                    let inner_html = name_el.inner_html().replace("<br>", " ");
-                    let name_vec = re
-                        .split(&inner_html)
-                        .filter(|item| {
-                            *item != ""
-                                && (item.contains("(BIO)") || !item.starts_with("("))
-                                && !item.contains("=")
-                        })
-                        .collect::<Vec<&str>>();
-                    let name_str = name_vec.join("").trim().split_whitespace().join(" ");
-                    decode(name_str.as_bytes()).to_string()
-                })?;
+        .filter_map(|day_container| day_container.attr("data-day"))
+        .map(|date| date.to_string())
+        .collect();
+
+    let name_regex =
+        Regex::new(r#"</?\w+((\s+\w+(\s*=\s*(?:".*?"|'.*?'|[^'">\s]+))?)+\s*|\s*)/?>"#)
+            .expect("Name Regex failed");
+
+    let mut meals = vec![];
+    for day_container in document.select(&day_element_selector) {
+        let date = day_container
+            .attr("data-day")
+            .expect("Day element selector only selects days with date");
+
+        for meal_info in day_container.select(&meal_info_selector) {
+            let Some(name_el) = meal_info.select(&name_selector).next() else {
+                warnings.push(
+                    WarningKind::MissingName,
+                    "Failed to select meal name element",
+                    Some(meal_info.html()),
+                );
+                continue;
+            };
+            let inner_html = name_el.inner_html().replace("<br>", " ");
+            let name_vec = name_regex
+                .split(&inner_html)
+                .filter(|item| {
+                    !item.is_empty()
+                        && (item.contains("(BIO)") || !item.starts_with("("))
+                        && !item.contains("=")
+                })
+                .collect::<Vec<&str>>();
+            let name_str = name_vec.join("").split_whitespace().join(" ");
+            let name = match decode(name_str.as_bytes()).to_string() {
+                Ok(name) if !name.is_empty() => name,
+                _ => {
+                    warnings.push(
+                        WarningKind::MissingName,
+                        "Meal name is empty or not decodable",
+                        Some(name_el.html()),
+                    );
+                    continue;
+                }
+            };
 
             let vegan = meal_info
                 .attr("data-arten")
@@ -106,92 +396,160 @@ async fn scrape_meals_of_week(
                 .is_some_and(|a| a.contains("ve"))
                 || vegan;
 
-            let meal_location = if !location.is_double() {
+            let meal_location = if !locations.is_double(location) {
                 location
             } else {
-                meal_info
+                let sibling = meal_info
                     .select(&menu_location_selector)
                     .next()
                     .map(|e| {
                         if e.inner_html().contains("Mensa") || e.inner_html().contains("Canteen") {
-                            location.get_mensa_option()
+                            location.mensa_sibling()
                         } else {
-                            location.get_cafeteria_option()
+                            location.cafeteria_sibling()
                         }
                     })
-                    .ok_or(anyhow!("Failed to select menu location"))?
+                    .and_then(|code| locations.get(code));
+                match sibling {
+                    Some(sibling) => sibling,
+                    None => {
+                        warnings.push(
+                            WarningKind::UnknownMenuLocation,
+                            format!("Failed to select menu location of '{name}'"),
+                            Some(meal_info.html()),
+                        );
+                        continue;
+                    }
+                }
             };
 
-            let raw_allergens = meal_info
-                .attr("data-allergene")
-                .ok_or(anyhow!("Failed to get allergen attr"))?;
+            let raw_allergens = match meal_info.attr("data-allergene") {
+                Some(raw_allergens) => raw_allergens,
+                None => {
+                    warnings.push(
+                        WarningKind::MissingAllergens,
+                        format!("Failed to get allergen attr of '{name}'"),
+                        Some(meal_info.html()),
+                    );
+                    ""
+                }
+            };
 
-            // TODO: Do not clone, but use a reference into the allergen vec.
-            let mut meal_allergens: Vec<Allergen> = allergens
+            let language_allergens: Vec<&Allergen> = allergens
                 .iter()
-                .filter(|allergen| {
-                    raw_allergens.contains(&allergen.code)
-                        && allergen.language.code == language.code
-                })
-                .map(|a| a.clone())
+                .filter(|allergen| allergen.language.code == language.code)
+                .collect();
+
+            for code in raw_allergens
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|code| !code.is_empty())
+            {
+                if !language_allergens.iter().any(|a| a.code == code) {
+                    warnings.push(
+                        WarningKind::UnknownAllergen,
+                        format!("Unknown allergen code '{code}' of '{name}'"),
+                        Some(meal_info.html()),
+                    );
+                }
+            }
+
+            // TODO: Do not clone, but use a reference into the allergen vec.
+            let mut meal_allergens: Vec<Allergen> = language_allergens
+                .into_iter()
+                .filter(|allergen| raw_allergens.contains(&allergen.code))
+                .cloned()
                 .collect();
 
             if meal_info
                 .attr("data-arten")
                 .is_some_and(|a| a.contains("A"))
             {
-                meal_allergens.push(Allergen::alkohol(language.code.as_str()));
+                meal_allergens.push(Allergen::alkohol(language_config));
             }
 
-            let date = date_str.ok_or(anyhow!("Failed to extract date info"))?;
-
-            let price = meal_info
-                .select(&price_selector)
-                .next()
+            let price_el = meal_info.select(&price_selector).next();
+            let price = match price_el
                 .ok_or(anyhow!("Failed to select price element"))
-                .map(|e| e.text().map(|n| n).join("/"))
-                .and_then(|html| decode(html.as_bytes()).to_string())?
-                .try_into()
-                .unwrap_or_default();
+                .map(|e| e.text().join("/"))
+                .and_then(|html| decode(html.as_bytes()).to_string())
+                .and_then(Prices::try_from)
+            {
+                Ok(price) => price,
+                Err(e) => {
+                    warnings.push(
+                        WarningKind::UnparsablePrice,
+                        format!("Price of '{name}' unparsable: {e}"),
+                        Some(price_el.unwrap_or(meal_info).html()),
+                    );
+                    Prices::default()
+                }
+            };
 
-            Ok(Meal {
+            meals.push(Meal {
                 name,
                 price,
                 vegan,
                 vegetarian,
-                location: meal_location.into(),
+                location: APILocation::from(meal_location).localized(language_config),
                 allergens: meal_allergens,
                 date: date.to_string(),
                 language: language.clone(),
-            })
-        })
-        .collect()
+            });
+        }
+    }
+
+    let slice = Slice {
+        location: location.code.clone(),
+        locations: locations
+            .sharing_page(location)
+            .map(|l| l.code.clone())
+            .collect(),
+        week_start: week_start(week),
+        language: language.code.clone(),
+        days: days.clone(),
+        last_updated: Utc::now(),
+    };
+
+    let menu = Menu {
+        days,
+        meals,
+        warnings: warnings.into_warnings(),
+        slices: vec![slice],
+    };
+    pages.store_menu(&url, allergens, &menu);
+    Ok(menu)
 }
 
-pub async fn scrape_allergens() -> anyhow::Result<Vec<Allergen>> {
-    let mut allergens = scrape_lanuage_allergens(
-        Language::german(),
-        "https://studentenwerk.sh/de/mensen-in-luebeck?ort=3&mensa=8&nw=0#mensaplan",
-    )
-    .await?;
-    let mut english_allergens = scrape_lanuage_allergens(
-        Language::english(),
-        "https://studentenwerk.sh/en/canteens-in-luebeck?ort=3&mensa=8&nw=0#mensaplan",
-    )
-    .await?;
-
-    allergens.append(&mut english_allergens);
-    allergens.push(Allergen::alkohol("de"));
-    allergens.push(Allergen::alkohol("en"));
+async fn scrape_allergens(
+    languages: &Languages,
+    pages: &PageFetcher<'_>,
+) -> anyhow::Result<Vec<Allergen>> {
+    let futures = languages.iter().map(|language| {
+        let url = format!(
+            "https://studentenwerk.sh/{}/{}?ort=3&mensa=8&nw=0#mensaplan",
+            language.code, language.allergen_slug
+        );
+        async move {
+            let mut allergens = scrape_lanuage_allergens(language.language(), &url, pages).await?;
+            allergens.push(Allergen::alkohol(language));
+            anyhow::Ok(allergens)
+        }
+    });
+
+    let allergens = join_all(futures)
+        .await
+        .into_iter()
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    Ok(allergens)
+    Ok(allergens.into_iter().flatten().collect())
 }
 
 async fn scrape_lanuage_allergens(
     lang: Language,
-    url: &'static str,
+    url: &str,
+    pages: &PageFetcher<'_>,
 ) -> anyhow::Result<Vec<Allergen>> {
-    let html = reqwest::get(url).await?.text().await?;
+    let html = pages.get(url).await?;
 
     let document = scraper::Html::parse_document(&html);
 
@@ -203,17 +561,84 @@ async fn scrape_lanuage_allergens(
 
     let allergens: Vec<Allergen> = parent_element
         .child_elements()
-        .map(|e| -> Option<Allergen> {
+        .filter_map(|e| -> Option<Allergen> {
             let code = e.attr("data-wert")?.to_string();
-            let name = e.child_elements().skip(1).next()?.inner_html();
+            let name = e.child_elements().nth(1)?.inner_html();
             Some(Allergen {
                 code,
                 name,
                 language: lang.clone(),
             })
         })
-        .filter_map(|a| a)
         .collect();
 
     Ok(allergens)
 }
+
+pub(crate) async fn scrape_opening_hours(
+    source: &OpeningHoursSource,
+    pages: &PageFetcher<'_>,
+) -> anyhow::Result<OpeningHours> {
+    let html = pages.get(&source.url).await?;
+
+    let document = Html::parse_document(&html);
+
+    let selector = Selector::parse(&source.selector)
+        .map_err(|e| anyhow!("Opening hours selector failed: {e}"))?;
+    let text = document.select(&selector).flat_map(|e| e.text()).join(" ");
+
+    Ok(parse_opening_hours(&decode(text.as_bytes()).to_string()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, FixtureMeal};
+
+    #[tokio::test]
+    async fn reports_unknown_allergens_with_the_html_of_the_meal() {
+        let monday = fixtures::days(0)[0].clone();
+        let meal = FixtureMeal {
+            date: &monday,
+            name: "Linsen-Dal",
+            art: "",
+            allergens: "Gl,Xy",
+            arten: "vn",
+            price: "2,50 € / 3,50 € / 4,50 €",
+        };
+        let snapshot = fixtures::snapshot(
+            "unknown-allergen",
+            Weeks::Fixed(1),
+            &[
+                (
+                    fixtures::allergen_url("de"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                (
+                    fixtures::allergen_url("en"),
+                    fixtures::allergen_page("Gluten"),
+                ),
+                (
+                    fixtures::menu_url("de", 9, 0),
+                    fixtures::menu_page(&[&monday], &[meal]),
+                ),
+                (fixtures::menu_url("en", 9, 0), String::new()),
+                (fixtures::menu_url("de", 8, 0), String::new()),
+                (fixtures::menu_url("en", 8, 0), String::new()),
+            ],
+        );
+        let data = fixtures::scraper()
+            .with_source(PageSource::Replay(snapshot))
+            .fetch()
+            .await
+            .unwrap();
+
+        let [warning] = data.get_warnings().as_slice() else {
+            panic!("{:?}", data.get_warnings());
+        };
+        assert_eq!(warning.get_kind(), WarningKind::UnknownAllergen);
+        let html = warning.get_html().unwrap();
+        assert!(html.contains(r#"data-allergene="Gl,Xy""#), "{html}");
+        assert!(html.contains("Linsen-Dal"), "{html}");
+    }
+}
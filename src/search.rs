@@ -0,0 +1,186 @@
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use strsim::levenshtein;
+
+/// A parsed full-text search over meal names.
+/// Matching ignores case, umlauts and word endings and tolerates small typos.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct SearchQuery {
+    text: String,
+    /// The spellings of every word of the query
+    tokens: Vec<Vec<String>>,
+}
+
+impl From<String> for SearchQuery {
+    fn from(text: String) -> Self {
+        Self {
+            tokens: words(&text)
+                .iter()
+                .map(|word| {
+                    [word.clone(), fold_transliterations(word)]
+                        .iter()
+                        .map(|spelling| stem(spelling).to_string())
+                        .unique()
+                        .collect()
+                })
+                .collect(),
+            text,
+        }
+    }
+}
+
+impl From<SearchQuery> for String {
+    fn from(value: SearchQuery) -> Self {
+        value.text
+    }
+}
+
+impl SearchQuery {
+    /// Rates how well the text matches the query between 0 and 1.
+    /// Returns `None` if any word of the query is not found in the text.
+    pub fn score(&self, text: &str) -> Option<f32> {
+        if self.tokens.is_empty() {
+            return Some(1.0);
+        }
+
+        let text_tokens = tokenize(text);
+        let total = self
+            .tokens
+            .iter()
+            .map(|spellings| {
+                spellings
+                    .iter()
+                    .flat_map(|query_token| {
+                        text_tokens
+                            .iter()
+                            .filter_map(|text_token| score_token(query_token, text_token))
+                    })
+                    .reduce(f32::max)
+            })
+            .sum::<Option<f32>>()?;
+
+        Some(total / self.tokens.len() as f32)
+    }
+}
+
+fn score_token(query_token: &str, text_token: &str) -> Option<f32> {
+    if query_token == text_token {
+        return Some(1.0);
+    }
+    if query_token.len() >= 3 && text_token.starts_with(query_token) {
+        return Some(0.9);
+    }
+    // Compound words like "Currywurst" should be found by "Wurst"
+    if query_token.len() >= 4 && text_token.contains(query_token) {
+        return Some(0.8);
+    }
+
+    let allowed_typos = match query_token.len() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    let distance = levenshtein(query_token, text_token);
+    (distance <= allowed_typos).then_some(0.7 - 0.1 * distance as f32)
+}
+
+/// Splits the text into normalized and stemmed words
+fn tokenize(text: &str) -> Vec<String> {
+    words(text)
+        .iter()
+        .map(|word| stem(word).to_string())
+        .collect()
+}
+
+/// Splits the text into normalized words
+fn words(text: &str) -> Vec<String> {
+    normalize(text)
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_string())
+        .collect()
+}
+
+/// Lowercases the text and folds umlauts and ß, e.g. "Käse" becomes "kase"
+pub fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .replace('ä', "a")
+        .replace('ö', "o")
+        .replace('ü', "u")
+        .replace('ß', "ss")
+}
+
+/// Folds umlauts written as "ae", "oe" and "ue", e.g. "kaese" becomes "kase".
+/// Only applied to the query as a second spelling, because words like "Bauer" contain these letters without an umlaut.
+fn fold_transliterations(word: &str) -> String {
+    word.replace("ae", "a")
+        .replace("oe", "o")
+        .replace("ue", "u")
+}
+
+/// Strips common german and english word endings, so that plural and singular forms match
+fn stem(token: &str) -> &str {
+    const SUFFIXES: [&str; 7] = ["ern", "en", "er", "es", "e", "n", "s"];
+
+    SUFFIXES
+        .iter()
+        .find_map(|suffix| {
+            token
+                .strip_suffix(suffix)
+                .filter(|stem| stem.chars().count() >= 4)
+        })
+        .unwrap_or(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, text: &str) -> Option<f32> {
+        SearchQuery::from(query.to_string()).score(text)
+    }
+
+    #[test]
+    fn ranks_exact_matches_above_prefixes_compounds_and_typos() {
+        let exact = score("Currywurst", "Currywurst mit Pommes").unwrap();
+        let prefix = score("Curry", "Currywurst mit Pommes").unwrap();
+        let compound = score("Wurst", "Currywurst mit Pommes").unwrap();
+        let typo = score("Curywurst", "Currywurst mit Pommes").unwrap();
+        assert!(exact > prefix);
+        assert!(prefix > compound);
+        assert!(compound > typo);
+    }
+
+    #[test]
+    fn requires_every_word_of_the_query() {
+        assert!(score("Currywurst Pommes", "Currywurst mit Pommes").is_some());
+        assert!(score("Currywurst Reis", "Currywurst mit Pommes").is_none());
+    }
+
+    #[test]
+    fn tolerates_typos_only_in_longer_words() {
+        assert!(score("Reis", "Reis").is_some());
+        assert!(score("Rais", "Reis").is_some());
+        assert!(score("Ris", "Reis").is_none());
+        assert!(score("Spagetti", "Spaghetti Bolognese").is_some());
+        assert!(score("Spgtti", "Spaghetti Bolognese").is_none());
+    }
+
+    #[test]
+    fn matches_umlauts_in_every_spelling() {
+        assert!(score("Käse", "Käsespätzle").is_some());
+        assert!(score("Kaese", "Käsespätzle").is_some());
+        assert!(score("Kase", "Käsespätzle").is_some());
+        assert!(score("Kloesse", "Thüringer Klöße").is_some());
+        assert!(score("Thueringer", "Thüringer Klöße").is_some());
+    }
+
+    #[test]
+    fn keeps_words_that_only_look_transliterated() {
+        assert!(score("Bauer", "Bauernfrühstück").is_some());
+        assert!(score("bar", "Bauernfrühstück").is_none());
+        assert!(score("fer", "Feuerwurst").is_none());
+        assert_eq!(normalize("Poesie"), "poesie");
+    }
+}